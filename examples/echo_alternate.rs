@@ -1,5 +1,5 @@
 use caprice::theme::{Theme, DEFAULT_THEME};
-use caprice::{Caprice, CapriceCommand};
+use caprice::{Caprice, CapriceCommand, CapriceInput};
 use crossterm::style::Color;
 use std::thread;
 use std::time::Duration;
@@ -30,7 +30,12 @@ fn main() {
     // the tokens send by caprice
     loop {
         // if we received a token from caprice
-        if let Ok(token) = rx.try_recv() {
+        if let Ok(input) = rx.try_recv() {
+            let token = match input {
+                CapriceInput::Keyword(token) => token,
+                // treat ctrl + d on an empty line as exit
+                _ => "exit".to_owned(),
+            };
             match token.as_str() {
                 // leave if the user types exit
                 "exit" => {
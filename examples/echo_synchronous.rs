@@ -1,4 +1,4 @@
-use caprice::{theme::DARK_BLUE, Caprice, CapriceCommand};
+use caprice::{theme::DARK_BLUE, Caprice, CapriceCommand, CapriceInput};
 fn main() {
     let mut caprice = Caprice::new()
         .set_prompt("!:") // set the prompt
@@ -15,7 +15,11 @@ fn main() {
 
     loop {
         // Block until we get the next keyword from `Caprice`.
-        let response = caprice.get().unwrap();
+        let response = match caprice.get().unwrap() {
+            CapriceInput::Keyword(keyword) => keyword,
+            // treat ctrl + d on an empty line as exit
+            _ => "exit".to_owned(),
+        };
         // keyword can contain arguments, split on spaces
         let mut args = response.as_str().trim_end().split(' ');
         // First arg is the keyword
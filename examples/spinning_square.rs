@@ -15,7 +15,7 @@ use piston::event_loop::*;
 use piston::input::*;
 use piston::window::WindowSettings;
 
-use caprice::{Caprice, CapriceCommand};
+use caprice::{Caprice, CapriceCommand, CapriceInput};
 
 pub struct App {
     gl: GlGraphics, // OpenGL drawing backend.
@@ -99,7 +99,12 @@ fn main() {
 
         if let Some(u) = e.update_args() {
             // check if we received a token from caprice
-            if let Ok(color) = rx.try_recv() {
+            if let Ok(input) = rx.try_recv() {
+                let color = match input {
+                    CapriceInput::Keyword(color) => color,
+                    // treat ctrl + d on an empty line as exit
+                    _ => "exit".to_owned(),
+                };
                 // and react accordingly
                 match color.as_str() {
                     "red_background" => app.bg_color = [1.0, 0.0, 0.0, 1.0],
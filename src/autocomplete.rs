@@ -1,6 +1,6 @@
-use crate::error::Result;
-use crate::terminal::Terminal;
-use crossterm::style::{Attribute, Color, SetForegroundColor};
+use crate::caprice::MatchingMode;
+use crate::unicode;
+use std::ops::Range;
 
 #[derive(Debug)]
 pub(crate) struct Autocomplete {
@@ -8,6 +8,13 @@ pub(crate) struct Autocomplete {
     common: String,
     pub(crate) tabbed: bool,
     pub(crate) tab_idx: usize,
+    // Whether keywords match the word as a subsequence, ranked by score,
+    // rather than by prefix.
+    pub(crate) fuzzy: bool,
+    pub(crate) matching: MatchingMode,
+    // Byte range of the input replaced by a suggestion, the word under the
+    // cursor.
+    pub(crate) range: Range<usize>,
 }
 
 impl Autocomplete {
@@ -17,9 +24,13 @@ impl Autocomplete {
             common: String::new(),
             tabbed: false,
             tab_idx: 0,
+            fuzzy: false,
+            matching: MatchingMode::CaseSensitive,
+            range: 0..0,
         }
     }
 
+    #[cfg(test)]
     pub(crate) fn get_common(&self) -> &String {
         &self.common
     }
@@ -30,10 +41,10 @@ impl Autocomplete {
 
     pub(crate) fn amortize(&mut self) {
         // get the length of the longest word in similar
-        if let Some(max_len) = self.keywords.iter().map(|x| x.len()).max() {
-            // amortise the length of every keyword to the longest one
+        if let Some(max_width) = self.keywords.iter().map(|x| unicode::width(x)).max() {
+            // amortise the display width of every keyword to the longest one
             for word in self.keywords.iter_mut() {
-                for _ in 0..max_len - word.len() {
+                for _ in 0..max_width - unicode::width(word) {
                     word.push(' ');
                 }
             }
@@ -69,18 +80,35 @@ impl<'a> Autocomplete {
             return;
         }
 
-        let mut similar: Vec<String> = keywords
-            .iter()
-            .filter(|x| x.starts_with(word))
-            .cloned()
-            .collect();
-
-        self.keywords = similar.clone();
-        self.common = if let Some(common) = return_common_str_from_sorted_collection(&mut similar) {
-            common
+        let ignore_case = self.matching.ignores_case(word);
+        let mut similar: Vec<String> = if self.fuzzy {
+            let mut scored: Vec<(i64, &String)> = keywords
+                .iter()
+                .filter_map(|x| fuzzy_score(word, x, ignore_case).map(|score| (score, x)))
+                .collect();
+            // best score first, then shortest
+            scored.sort_by_key(|&(score, x)| (std::cmp::Reverse(score), x.len()));
+            scored.into_iter().map(|(_, x)| x.clone()).collect()
         } else {
-            String::new()
+            keywords
+                .iter()
+                .filter(|x| strip_prefix(x, word, ignore_case).is_some())
+                .cloned()
+                .collect()
         };
+
+        self.keywords = similar.clone();
+        self.common =
+            return_common_str_from_sorted_collection(&mut similar, ignore_case).unwrap_or_default();
+    }
+
+    // Suggests every keyword, used to list the candidates of an argument
+    // before any of it is typed.
+    pub(crate) fn list(&mut self, keywords: &'a [String]) {
+        let mut similar = keywords.to_vec();
+        self.keywords = similar.clone();
+        self.common =
+            return_common_str_from_sorted_collection(&mut similar, false).unwrap_or_default();
     }
 
     pub(crate) fn get_current_tabbed_autocomplete(&self) -> Option<String> {
@@ -93,36 +121,117 @@ impl<'a> Autocomplete {
         }
     }
 
-    // displays an autocomplete suggestion
-    pub(crate) fn print_same_line_autocompleted(
-        &self,
-        color: Color,
-        buffer: &str,
-        terminal: &Terminal,
-    ) -> Result<()> {
-        if !self.common.is_empty() {
-            terminal.save_cursor()?;
-
-            // print in DarkGreen the autocompleted part
-            print!(
-                "{}{}{}",
-                SetForegroundColor(color),
-                self.common.split_at(buffer.len()).1,
-                Attribute::Reset
-            );
-
-            terminal.restore_cursor()?;
-        } else {
-            // clear everything left of the cursor
-            terminal.clear_from_cursor()?;
+    // the part of the common suggestion not typed yet, displayed after the
+    // word under the cursor
+    pub(crate) fn hint<'b>(&'b self, buffer: &str) -> &'b str {
+        match buffer.get(self.range.clone()) {
+            Some(word) => strip_prefix(&self.common, word, self.matching.ignores_case(word))
+                .unwrap_or_default(),
+            None => "",
+        }
+    }
+
+    // Returns the input with the word under the cursor replaced by the
+    // suggestion, and the position of the cursor after it.
+    pub(crate) fn complete(&self, buffer: &str, suggestion: &str) -> (String, usize) {
+        let line = format!(
+            "{}{}{}",
+            &buffer[..self.range.start],
+            suggestion,
+            &buffer[self.range.end..]
+        );
+        (line, self.range.start + suggestion.len())
+    }
+}
+
+// Fuzzy matching scores, following fzf: each matched char scores, matches
+// at the start of a word or right after the previous match score a bonus,
+// and skipped chars between matches are penalized.
+const SCORE_MATCH: i64 = 16;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CONSECUTIVE: i64 = 4;
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP_EXTENSION: i64 = 1;
+
+// Scores the best match of the pattern as a subsequence of the candidate,
+// `None` if the candidate doesn't contain it.
+pub(crate) fn fuzzy_score(pattern: &str, candidate: &str, ignore_case: bool) -> Option<i64> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let candidate: Vec<char> = candidate.chars().collect();
+    if pattern.is_empty() {
+        return Some(0);
+    }
+
+    // bonus for matching each char of the candidate, the first char of
+    // the pattern counts its boundary bonus twice
+    let bonus: Vec<i64> = (0..candidate.len())
+        .map(|j| {
+            let boundary = match j.checked_sub(1).map(|prev| candidate[prev]) {
+                None => true,
+                Some(prev) => {
+                    !prev.is_alphanumeric() || (prev.is_lowercase() && candidate[j].is_uppercase())
+                }
+            };
+            if boundary {
+                BONUS_BOUNDARY
+            } else {
+                0
+            }
+        })
+        .collect();
+
+    // scores[j] is the best score of the pattern so far, with its last char
+    // matched at position j of the candidate
+    let mut scores: Vec<Option<i64>> = (0..candidate.len())
+        .map(|j| {
+            chars_eq(candidate[j], pattern[0], ignore_case).then(|| SCORE_MATCH + 2 * bonus[j])
+        })
+        .collect();
+    for &c in &pattern[1..] {
+        scores = (0..candidate.len())
+            .map(|j| {
+                if !chars_eq(candidate[j], c, ignore_case) {
+                    return None;
+                }
+                (0..j)
+                    .filter_map(|k| {
+                        let gap = (j - k - 1) as i64;
+                        let transition = if gap == 0 {
+                            BONUS_CONSECUTIVE.max(bonus[j])
+                        } else {
+                            bonus[j] - PENALTY_GAP_START - (gap - 1) * PENALTY_GAP_EXTENSION
+                        };
+                        scores[k].map(|score| score + SCORE_MATCH + transition)
+                    })
+                    .max()
+            })
+            .collect();
+    }
+    scores.into_iter().flatten().max()
+}
+
+fn chars_eq(a: char, b: char, ignore_case: bool) -> bool {
+    a == b || (ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+}
+
+// Returns the rest of the string if it starts with the prefix.
+pub(crate) fn strip_prefix<'s>(s: &'s str, prefix: &str, ignore_case: bool) -> Option<&'s str> {
+    let mut chars = s.char_indices();
+    for p in prefix.chars() {
+        match chars.next() {
+            Some((_, c)) if chars_eq(c, p, ignore_case) => (),
+            _ => return None,
         }
-        Ok(())
     }
+    Some(chars.as_str())
 }
 
 // returns the common str slice of a collection of str slices
 // returns None if no common slice can be found
-fn return_common_str_from_sorted_collection(collection: &mut [String]) -> Option<String> {
+fn return_common_str_from_sorted_collection(
+    collection: &mut [String],
+    ignore_case: bool,
+) -> Option<String> {
     // take the first element of the sorted list and check if the rest of the elements start with
     // if not remove last character and repeat
     let copied_collection = collection.to_owned();
@@ -130,12 +239,12 @@ fn return_common_str_from_sorted_collection(collection: &mut [String]) -> Option
     while let Some(first) = collection.first_mut() {
         if copied_collection
             .iter()
-            .all(|x| x.starts_with(first.as_str()))
+            .all(|x| strip_prefix(x, first, ignore_case).is_some())
         {
             return Some(first.clone());
         } else {
-            // else remove the last character and try again
-            first.pop();
+            // else remove the last grapheme and try again
+            unicode::pop_grapheme(first);
         }
     }
     // if we tried all slices, there is no common str
@@ -261,6 +370,65 @@ mod tests {
         assert_eq!(autocompleted.get_common(), &"some_word".to_owned());
     }
 
+    #[test]
+    fn matching_modes() {
+        let keywords = vec![
+            "Set_Color".to_owned(),
+            "set_theme".to_owned(),
+            "list".to_owned(),
+        ];
+        let mut autocomplete = Autocomplete::new();
+        autocomplete.update("set", &keywords);
+        assert_eq!(autocomplete.get_keywords(), &vec!["set_theme"]);
+
+        autocomplete.matching = MatchingMode::CaseInsensitive;
+        autocomplete.update("SET", &keywords);
+        assert_eq!(autocomplete.get_keywords(), &vec!["Set_Color", "set_theme"]);
+        // the common part keeps the casing of the keywords
+        assert_eq!(autocomplete.get_common(), "Set_");
+        autocomplete.range = 0..3;
+        assert_eq!(autocomplete.hint("SET"), "_");
+
+        // smart case is only sensitive to input with upper case letters
+        autocomplete.matching = MatchingMode::SmartCase;
+        autocomplete.update("set", &keywords);
+        assert_eq!(autocomplete.get_keywords(), &vec!["Set_Color", "set_theme"]);
+        autocomplete.update("Set", &keywords);
+        assert_eq!(autocomplete.get_keywords(), &vec!["Set_Color"]);
+
+        autocomplete.fuzzy = true;
+        autocomplete.update("sc", &keywords);
+        assert_eq!(autocomplete.get_keywords(), &vec!["Set_Color"]);
+    }
+
+    #[test]
+    fn fuzzy_matches() {
+        assert!(fuzzy_score("stk", "some_token", false).is_some());
+        assert!(fuzzy_score("sot", "some_other_token", false).is_some());
+        assert!(fuzzy_score("ts", "some_token", false).is_none());
+        assert!(fuzzy_score("some_tokens", "some_token", false).is_none());
+
+        // word starts and consecutive chars rank first
+        let keywords = vec![
+            "list".to_owned(),
+            "set_theme".to_owned(),
+            "show_time".to_owned(),
+            "sort_items".to_owned(),
+        ];
+        let mut autocomplete = Autocomplete::new();
+        autocomplete.fuzzy = true;
+        autocomplete.update("st", &keywords);
+        assert_eq!(
+            autocomplete.get_keywords(),
+            &vec!["set_theme", "show_time", "sort_items", "list"]
+        );
+        autocomplete.update("sti", &keywords);
+        assert_eq!(
+            autocomplete.get_keywords(),
+            &vec!["show_time", "sort_items"]
+        );
+    }
+
     #[test]
     fn amortized() {
         // normal conditions
@@ -277,6 +445,15 @@ mod tests {
         autocomplete.amortize();
         assert_eq!(autocomplete.get_keywords(), &vec!["_aa", "_bb", "_cc"]);
 
+        // wide and multi byte chars
+        let vec = vec!["_漢".to_owned(), "_é".to_owned(), "_abcd".to_owned()];
+        autocomplete.update(&word, &vec);
+        autocomplete.amortize();
+        assert_eq!(
+            autocomplete.get_keywords(),
+            &vec!["_漢  ", "_é   ", "_abcd"]
+        );
+
         // empty vec
         let vec = Vec::with_capacity(0);
         autocomplete.update(&word, &vec);
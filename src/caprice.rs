@@ -1,20 +1,38 @@
+use crate::completion::Completer;
 use crate::engine::Executor;
 use crate::error::Result;
+use crate::keymap::Keymap;
 use crate::theme::Theme;
 use crossterm::style::Attribute;
+use std::path::PathBuf;
 use std::sync::mpsc;
 use std::thread::{self, JoinHandle};
 
 /// Return type of `Caprice::run`.
 /// Sender can be used to send commands to the `Caprice` REPL.
-/// Receiver can be used to receive the keywords typed by the user.
+/// Receiver can be used to receive the keywords typed by the user, or the end of input.
 /// Handle can be used to join caprices' thread.
 pub type CapriceMessage = (
     mpsc::Sender<Option<CapriceCommand>>,
-    mpsc::Receiver<String>,
+    mpsc::Receiver<CapriceInput>,
     JoinHandle<Result<()>>,
 );
 
+/// Input received from the `Caprice` REPL. A `CapriceCommand` must be sent
+/// back for each received input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CapriceInput {
+    /// A keyword typed by the user, followed by its arguments.
+    Keyword(String),
+    /// The user pressed ctrl+d on an empty line. It is up to the invoking
+    /// application whether to exit.
+    Eof,
+    /// The secret requested with `CapriceCommand::ReadSecret`, or `None` if
+    /// the user cancelled with escape or ctrl+c.
+    Secret(Option<String>),
+}
+
 /// Commands that can be sent to the `Caprice` REPL by the invoking application.
 #[derive(Debug)]
 pub enum CapriceCommand {
@@ -22,6 +40,76 @@ pub enum CapriceCommand {
     Println(String),
     /// Exit the `Caprice` terminal.
     Exit,
+    /// Read a secret, such as a password, on a new line showing the provided
+    /// prompt. The secret is received as `CapriceInput::Secret` and never
+    /// reaches the autocomplete or any history.
+    ReadSecret { prompt: String, echo: SecretEcho },
+}
+
+/// How the characters of a secret are displayed while it is typed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretEcho {
+    /// Display a `*` for each character.
+    Mask,
+    /// Display nothing.
+    Hidden,
+}
+
+/// How newlines in pasted text are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PastePolicy {
+    /// Submit each pasted line, as if it was typed followed by enter. Text
+    /// after the last newline is left on the prompt.
+    SubmitEachLine,
+    /// Join the pasted lines, separated by spaces.
+    Join,
+    /// Ignore pastes spanning several lines.
+    Reject,
+}
+
+/// How typed input is compared to keywords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchingMode {
+    /// Keywords must match the input casing.
+    CaseSensitive,
+    /// Casing is ignored.
+    CaseInsensitive,
+    /// Casing is ignored, unless the input contains an upper case letter.
+    SmartCase,
+}
+
+impl MatchingMode {
+    pub(crate) fn ignores_case(&self, input: &str) -> bool {
+        match self {
+            MatchingMode::CaseSensitive => false,
+            MatchingMode::CaseInsensitive => true,
+            MatchingMode::SmartCase => !input.chars().any(char::is_uppercase),
+        }
+    }
+}
+
+/// Which characters can be typed or pasted into the `Caprice` REPL.
+/// Other characters are ignored.
+#[derive(Debug, Clone, Copy)]
+pub enum CharPolicy {
+    /// Alphanumeric characters, `/`, `_`, spaces, the `!`, `$` and `-`
+    /// characters of history references and the backslash, quotes and
+    /// brackets continuing multi-line input.
+    Restricted,
+    /// Every printable character.
+    Printable,
+    /// Characters for which the predicate returns `true`.
+    Custom(fn(char) -> bool),
+}
+
+impl CharPolicy {
+    pub(crate) fn accepts(&self, c: char) -> bool {
+        match self {
+            CharPolicy::Restricted => c.is_alphanumeric() || "/_ !$-\\\"'()[]{}".contains(c),
+            CharPolicy::Printable => !c.is_control(),
+            CharPolicy::Custom(predicate) => predicate(c),
+        }
+    }
 }
 
 /// Builds and initializes the `Caprice` terminal
@@ -36,8 +124,10 @@ impl CapriceBuilder {
     /// See the `echo` and `echo_synchronous` examples on how to work with an initialized `Caprice`
     /// REPL.**
     pub fn init(mut self) -> Result<Caprice> {
+        self.caprice.executor.history.load()?;
         // Caprice needs the terminal's raw mode to be enabled
         self.caprice.executor.terminal.enable_raw_mode()?;
+        self.caprice.executor.terminal.enable_bracketed_paste()?;
         // set alternate mode
         if self.caprice.executor.alternate_screen {
             self.caprice.executor.terminal.enable_alternate_screen()?
@@ -62,12 +152,178 @@ impl CapriceBuilder {
         self
     }
 
+    /// Sets the candidates completing the arguments of a keyword, the words
+    /// typed after it.
+    ///
+    /// ```rust,no_run
+    /// use caprice::Caprice;
+    ///
+    /// let caprice = Caprice::new()
+    ///     .set_keywords(vec!["color", "exit"])
+    ///     .set_keyword_args("color", ["red", "green"])
+    ///     .init();
+    /// ```
+    pub fn set_keyword_args(
+        mut self,
+        keyword: &'static str,
+        args: impl IntoIterator<Item = &'static str>,
+    ) -> Self {
+        self.caprice.executor.set_keyword_args(keyword, args);
+        self
+    }
+
+    /// Sets a short help string describing a keyword. Described keywords are
+    /// suggested one per row, followed by their description cut to the width
+    /// of the terminal:
+    ///
+    /// ```text
+    /// connect     -- open a session to a device
+    /// disconnect  -- close the current session
+    /// ```
+    pub fn set_keyword_description(
+        mut self,
+        keyword: &'static str,
+        description: &'static str,
+    ) -> Self {
+        self.caprice
+            .executor
+            .set_keyword_description(keyword, description);
+        self
+    }
+
+    /// Sets a completer for an argument of a keyword, `index` 0 being the
+    /// first argument. It takes precedence over the candidates set with
+    /// `set_keyword_args()` for this argument.
+    pub fn set_keyword_arg_completer(
+        mut self,
+        keyword: &'static str,
+        index: usize,
+        completer: impl Completer + 'static,
+    ) -> Self {
+        self.caprice
+            .executor
+            .set_keyword_arg_completer(keyword, index, Box::new(completer));
+        self
+    }
+
+    /// Sets a completer computing candidates from the current line. Its
+    /// candidates are used instead of the keywords and their arguments when
+    /// it returns some. A submitted line is sent like a keyword when its
+    /// first word is a keyword, or when `Completer::is_keyword()` accepts it.
+    pub fn completer(mut self, completer: impl Completer + 'static) -> Self {
+        self.caprice.executor.completer = Some(Box::new(completer));
+        self
+    }
+
     /// Enables Alternate Screen rendering
     pub fn enable_alternate_screen(mut self) -> Self {
         self.caprice.executor.alternate_screen = true;
         self
     }
 
+    /// Enables vi editing mode.
+    ///
+    /// Each line starts in insert mode, pressing escape switches to normal
+    /// mode. The current mode is shown in front of the prompt.
+    pub fn enable_vi_mode(mut self) -> Self {
+        self.caprice.executor.scanner.enable_vi_mode();
+        self
+    }
+
+    /// Sets the key bindings, replacing the default ones.
+    /// See `Keymap` on how to rebind keys.
+    pub fn keymap(mut self, keymap: Keymap) -> Self {
+        self.caprice.executor.scanner.keymap = keymap;
+        self
+    }
+
+    /// Sets how newlines in pasted text are handled.
+    /// Defaults to `PastePolicy::SubmitEachLine`.
+    pub fn paste_policy(mut self, policy: PastePolicy) -> Self {
+        self.caprice.executor.scanner.paste_policy = policy;
+        self
+    }
+
+    /// Sets which characters are accepted as input.
+    /// Defaults to `CharPolicy::Restricted`.
+    ///
+    /// ## Note
+    /// Keywords must still match "^[_a-zA-Z][A-Za-z_0-9]*$", other characters
+    /// are only useful in keyword arguments.
+    pub fn char_policy(mut self, policy: CharPolicy) -> Self {
+        self.caprice.executor.scanner.char_policy = policy;
+        self
+    }
+
+    /// Keeps the history in the provided file, so that it persists across
+    /// sessions. The file is loaded at `init()` and each submitted line is
+    /// appended to it. Concurrent sessions lock the file while writing.
+    ///
+    /// Lines of the file that aren't valid UTF-8 are skipped. Errors writing
+    /// the file are printed, the session goes on without persisting them.
+    pub fn history_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.caprice.executor.history.file = Some(path.into());
+        self
+    }
+
+    /// Sets the maximum number of lines kept in the history, and in the
+    /// history file. Defaults to 1000.
+    pub fn history_size(mut self, size: usize) -> Self {
+        self.caprice.executor.history.max_size = size;
+        self
+    }
+
+    /// Doesn't add a line to the history if it repeats the previous one.
+    pub fn history_ignore_dups(mut self) -> Self {
+        self.caprice.executor.history.ignore_dups = true;
+        self
+    }
+
+    /// Doesn't add lines starting with a space to the history.
+    pub fn history_ignore_space(mut self) -> Self {
+        self.caprice.executor.history.ignore_space = true;
+        self
+    }
+
+    /// Sets how the input is compared to keywords, both when completing and
+    /// when checking a submitted line. Defaults to
+    /// `MatchingMode::CaseSensitive`.
+    ///
+    /// Keywords are completed, and sent, with their own casing whatever the
+    /// casing of the input.
+    pub fn matching_mode(mut self, mode: MatchingMode) -> Self {
+        self.caprice.executor.autocomplete.matching = mode;
+        self
+    }
+
+    /// Matches keywords containing the typed characters in order, rather than
+    /// starting with them, so that `stk` matches `some_token`. Suggestions are
+    /// ranked, favouring matches at the start of words and consecutive
+    /// characters.
+    pub fn enable_fuzzy_matching(mut self) -> Self {
+        self.caprice.executor.autocomplete.fuzzy = true;
+        self
+    }
+
+    /// Suggests the most recent history line starting with the input, shown
+    /// after the cursor in place of the autocomplete hint. Pressing the
+    /// right arrow or end accepts the suggestion.
+    pub fn enable_history_suggestions(mut self) -> Self {
+        self.caprice.executor.history_suggestions = true;
+        self
+    }
+
+    /// Disables the expansion of history references.
+    ///
+    /// By default `!!` is replaced by the previous line, `!n` by the nth line
+    /// of the history, `!-n` by the nth previous line, `!prefix` by the most
+    /// recent line starting with the prefix and `!$` by the last argument of
+    /// the previous line. The expanded line is echoed before being sent.
+    pub fn disable_history_expansion(mut self) -> Self {
+        self.caprice.executor.history_expansion = false;
+        self
+    }
+
     /// Disables exiting the REPL when pressing ctrl+c
     pub fn disable_ctrl_c(mut self) -> Self {
         self.caprice.executor.scanner.enable_ctrl_c = false;
@@ -85,12 +341,27 @@ impl CapriceBuilder {
         self.caprice.executor.prompt = prompt;
         self
     }
+
+    /// Sets the prompt displayed on the continuation lines of a multi-line
+    /// input. Defaults to `..`.
+    ///
+    /// Input continues on a new line instead of being submitted when it ends
+    /// in a backslash, or when it leaves quotes or brackets open. The whole
+    /// input is then received as a single keyword. A line continued with a
+    /// backslash is joined to the next one without the backslash, other lines
+    /// are joined with a newline.
+    pub fn set_continuation_prompt(mut self, prompt: &'static str) -> Self {
+        self.caprice.executor.continuation_prompt = prompt;
+        self
+    }
 }
 
 /// The main object of the `Caprice` REPL
 #[derive(Debug)]
 pub struct Caprice {
     executor: Executor,
+    // A secret read by `send`, returned by the next call to `get`.
+    pending: Option<CapriceInput>,
 }
 
 impl Caprice {
@@ -100,6 +371,7 @@ impl Caprice {
         CapriceBuilder {
             caprice: Caprice {
                 executor: Executor::new(),
+                pending: None,
             },
         }
     }
@@ -116,26 +388,31 @@ impl Caprice {
                 // The caprice thread blocks on the terminal executor.
                 // If a token is received, proceed to handling the host app's
                 // response.
-                if let Some(keyword) = self.executor.get_next_key_event()? {
-                    tx_keyword.send(keyword)?;
+                if let Some(input) = self.executor.get_next_key_event()? {
+                    tx_keyword.send(input)?;
                 } else {
                     // If no token is received wait for the next terminal event.
                     continue;
                 }
 
-                // Blocks for command.
+                // Blocks for command. Reading a secret sends it back and
+                // blocks for the next command.
                 // TODO: Push recv error to calling application.
-                if let Ok(command) = rx_command.recv() {
+                while let Ok(command) = rx_command.recv() {
                     match command {
                         Some(CapriceCommand::Println(msg)) => {
-                            self.executor.print_msg(&msg)?;
-                            self.executor.reset_prompt()?;
+                            self.executor.print_above_input(&msg)?;
+                            break;
+                        }
+                        Some(CapriceCommand::ReadSecret { prompt, echo }) => {
+                            let secret = self.executor.read_secret(&prompt, echo)?;
+                            tx_keyword.send(CapriceInput::Secret(secret))?;
                         }
                         Some(CapriceCommand::Exit) => {
                             self.executor.exec_exit()?;
                             return Ok(());
                         }
-                        None => continue,
+                        None => break,
                     }
                 }
             }
@@ -144,14 +421,17 @@ impl Caprice {
         Ok((tx_command, rx_keyword, handle))
     }
 
-    /// Returns the next keyword from the `Caprice` REPL. This method
-    /// __will block__ and should be used for synchronous programs. See also
-    /// the `echo_synchronous` example.
-    pub fn get(&mut self) -> Result<String> {
+    /// Returns the next keyword, or the end of input, from the `Caprice` REPL.
+    /// This method __will block__ and should be used for synchronous programs.
+    /// See also the `echo_synchronous` example.
+    pub fn get(&mut self) -> Result<CapriceInput> {
+        if let Some(input) = self.pending.take() {
+            return Ok(input);
+        }
         loop {
             // The caprice thread blocks on the terminal executor.
-            if let Some(keyword) = self.executor.get_next_key_event()? {
-                return Ok(keyword);
+            if let Some(input) = self.executor.get_next_key_event()? {
+                return Ok(input);
             } else {
                 // If no token is received wait for the next terminal event.
                 continue;
@@ -159,13 +439,28 @@ impl Caprice {
         }
     }
 
+    /// Reads a secret, such as a password, on a new line showing the provided
+    /// prompt. Returns `None` if the user cancelled with escape or ctrl+c.
+    /// This method __will block__ and should be used for synchronous programs.
+    ///
+    /// The secret never reaches the autocomplete or any history. The prompt,
+    /// and the line being edited if any, are redrawn below it.
+    pub fn get_secret(&mut self, prompt: &str, echo: SecretEcho) -> Result<Option<String>> {
+        self.executor.read_secret(prompt, echo)
+    }
+
     /// Pass a `CapriceCommand` to the `Caprice` REPL. This method is indented to
     /// be used on synchronous programs. See also the `echo_synchronous` example.
+    ///
+    /// A secret read with `CapriceCommand::ReadSecret` is returned by the next
+    /// call to `get`.
     pub fn send(&mut self, command: Option<CapriceCommand>) -> Result<()> {
         match command {
-            Some(CapriceCommand::Println(msg)) => {
-                self.executor.print_msg(&msg)?;
-                self.executor.reset_prompt()
+            Some(CapriceCommand::Println(msg)) => self.executor.print_above_input(&msg),
+            Some(CapriceCommand::ReadSecret { prompt, echo }) => {
+                let secret = self.executor.read_secret(&prompt, echo)?;
+                self.pending = Some(CapriceInput::Secret(secret));
+                Ok(())
             }
             Some(CapriceCommand::Exit) => {
                 self.executor.exec_exit()?;
@@ -184,6 +479,7 @@ impl Drop for Caprice {
         println!("{}", Attribute::Reset);
         self.executor.terminal.clear_from_cursor().unwrap();
         self.executor.terminal.flush().unwrap();
+        self.executor.terminal.disable_bracketed_paste().unwrap();
         self.executor.terminal.disable_raw_mode().unwrap();
     }
 }
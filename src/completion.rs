@@ -0,0 +1,409 @@
+use std::cell::RefCell;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Candidates completing the input, and the byte range of the input replaced
+/// by the accepted one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// Byte range of the line replaced by the accepted candidate. It must
+    /// contain the cursor.
+    pub range: Range<usize>,
+    /// Candidates for the range. Like keywords, they are matched against the
+    /// part of the range before the cursor.
+    pub candidates: Vec<String>,
+}
+
+impl Completion {
+    /// Creates a completion replacing the word under the cursor.
+    pub fn word(line: &str, cursor: usize, candidates: Vec<String>) -> Self {
+        Completion {
+            range: word_at(line, cursor),
+            candidates,
+        }
+    }
+}
+
+/// Provides completion candidates computed from the current line, for
+/// candidates that change while the REPL runs. It is set with
+/// `CapriceBuilder::completer()`.
+///
+/// Closures taking the line and the cursor position are completers:
+///
+/// ```rust,no_run
+/// use caprice::completion::Completion;
+/// use caprice::Caprice;
+///
+/// fn connected_devices() -> Vec<String> {
+///     vec!["dev0".to_owned(), "dev1".to_owned()]
+/// }
+///
+/// let caprice = Caprice::new()
+///     .set_keywords(vec!["connect", "exit"])
+///     .completer(|line: &str, cursor: usize| {
+///         // complete the argument of `connect` with the devices
+///         // connected right now
+///         if line.starts_with("connect ") && cursor > "connect".len() {
+///             Some(Completion::word(line, cursor, connected_devices()))
+///         } else {
+///             None
+///         }
+///     })
+///     .init();
+/// ```
+pub trait Completer: Send {
+    /// Returns the candidates completing `line` at `cursor`, a byte offset
+    /// into `line`. Returning `None` falls back to the keywords and their
+    /// arguments.
+    fn complete(&self, line: &str, cursor: usize) -> Option<Completion>;
+
+    /// Returns whether a submitted line starting with `word` is sent to the
+    /// host application like a keyword. Only the keywords are by default.
+    fn is_keyword(&self, _word: &str) -> bool {
+        false
+    }
+}
+
+impl<F> Completer for F
+where
+    F: Fn(&str, usize) -> Option<Completion> + Send,
+{
+    fn complete(&self, line: &str, cursor: usize) -> Option<Completion> {
+        self(line, cursor)
+    }
+}
+
+impl fmt::Debug for dyn Completer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Completer")
+    }
+}
+
+/// Completes filesystem paths, relative to the current directory or
+/// absolute, expanding a leading `~` to the home directory. Directories are
+/// completed with a trailing `/`. Whitespace, quotes and `!` in names are
+/// escaped with a backslash, unless the path is typed within quotes. The
+/// escaping is removed with [`unescape`].
+///
+/// The entries of a directory are listed once for the completions requested
+/// while typing a name, and listed again after a couple of seconds.
+///
+/// It can complete a keyword argument, set with
+/// `CapriceBuilder::set_keyword_arg_completer()`:
+///
+/// ```rust,no_run
+/// use caprice::completion::PathCompleter;
+/// use caprice::{Caprice, CharPolicy};
+///
+/// let caprice = Caprice::new()
+///     .set_keywords(vec!["load", "exit"])
+///     // complete the first argument of `load`
+///     .set_keyword_arg_completer("load", 0, PathCompleter::new())
+///     // accept the `.`, `~` and `\` characters of paths
+///     .char_policy(CharPolicy::Printable)
+///     .init();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PathCompleter {
+    show_hidden: bool,
+    // The last directory listed, reused as completions are requested on
+    // each key.
+    listing: RefCell<Option<Listing>>,
+}
+
+// How long the entries of a listed directory are reused.
+const LISTING_TTL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone)]
+struct Listing {
+    dir: PathBuf,
+    // Names of the entries, and whether they are directories.
+    entries: Vec<(String, bool)>,
+    read_at: Instant,
+}
+
+impl PathCompleter {
+    /// Creates a completer leaving out hidden files, unless their leading
+    /// dot is typed.
+    pub fn new() -> Self {
+        PathCompleter::default()
+    }
+
+    /// Also completes hidden files.
+    pub fn show_hidden(mut self) -> Self {
+        self.show_hidden = true;
+        self
+    }
+}
+
+impl Completer for PathCompleter {
+    fn complete(&self, line: &str, cursor: usize) -> Option<Completion> {
+        let range = word_at(line, cursor);
+        let typed = &line[range.start..cursor];
+        let quote = typed.chars().next().filter(|c| *c == '"' || *c == '\'');
+        let path = unescape(typed);
+
+        // the typed directory is kept as is, its entries starting with the
+        // rest of the path are listed
+        let (dir, name) = match path.rfind('/') {
+            Some(idx) => path.split_at(idx + 1),
+            None => ("", path.as_str()),
+        };
+        let entries = match dir {
+            "" => self.entries(Path::new("."))?,
+            dir => self.entries(&expand_home(dir, home_dir()))?,
+        };
+
+        let mut candidates: Vec<String> = entries
+            .into_iter()
+            .filter_map(|(file_name, is_dir)| {
+                if file_name.starts_with('.') && !self.show_hidden && !name.starts_with('.') {
+                    return None;
+                }
+                let path = format!("{}{}", dir, file_name);
+                Some(match (quote, is_dir) {
+                    // history references are expanded within double quotes
+                    (Some('"'), is_dir) => {
+                        let path = path.replace('!', "\\!");
+                        format!("\"{}{}", path, if is_dir { "/" } else { "\"" })
+                    }
+                    (Some(quote), true) => format!("{}{}/", quote, path),
+                    (Some(quote), false) => format!("{}{}{}", quote, path, quote),
+                    (None, true) => format!("{}/", escape(&path)),
+                    (None, false) => escape(&path),
+                })
+            })
+            .collect();
+        candidates.sort();
+
+        Some(Completion { range, candidates })
+    }
+}
+
+impl PathCompleter {
+    // Names of the entries of the directory and whether they are
+    // directories, reusing the last listing while it is recent.
+    fn entries(&self, dir: &Path) -> Option<Vec<(String, bool)>> {
+        let mut listing = self.listing.borrow_mut();
+        match listing.as_ref() {
+            Some(listing) if listing.dir == dir && listing.read_at.elapsed() < LISTING_TTL => (),
+            _ => {
+                let entries = fs::read_dir(dir)
+                    .ok()?
+                    .filter_map(|entry| entry.ok())
+                    .filter_map(|entry| {
+                        let file_name = entry.file_name().into_string().ok()?;
+                        // follow symbolic links to directories
+                        Some((file_name, entry.path().is_dir()))
+                    })
+                    .collect();
+                *listing = Some(Listing {
+                    dir: dir.to_owned(),
+                    entries,
+                    read_at: Instant::now(),
+                });
+            }
+        }
+        listing.as_ref().map(|listing| listing.entries.clone())
+    }
+}
+
+/// Returns the byte range of the word under the cursor, delimited by
+/// whitespace. Whitespace escaped with a backslash or within quotes is part
+/// of the word.
+pub fn word_at(line: &str, cursor: usize) -> Range<usize> {
+    words(line)
+        .into_iter()
+        .find(|word| word.start <= cursor && cursor <= word.end)
+        .unwrap_or(cursor..cursor)
+}
+
+// Byte ranges of the words of the line. Like the scanner's check for
+// unbalanced quotes, backslashes are literal within single quotes.
+pub(crate) fn words(line: &str) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (idx, c) in line.char_indices() {
+        if !escaped && quote.is_none() && c.is_whitespace() {
+            if let Some(start) = start.take() {
+                words.push(start..idx);
+            }
+            continue;
+        }
+        start.get_or_insert(idx);
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => (),
+            (_, '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '"' | '\'') => quote = Some(c),
+            _ => (),
+        }
+    }
+    if let Some(start) = start {
+        words.push(start..line.len());
+    }
+    words
+}
+
+/// Removes the quotes and backslashes escaping the characters of a word, as
+/// inserted by [`PathCompleter`].
+///
+/// ```rust
+/// use caprice::completion::unescape;
+///
+/// assert_eq!(unescape(r"my\ file"), "my file");
+/// assert_eq!(unescape(r#""a b"'c'"#), "a bc");
+/// ```
+pub fn unescape(word: &str) -> String {
+    let mut unescaped = String::new();
+    let mut quote = None;
+    let mut escaped = false;
+
+    for c in word.chars() {
+        if escaped {
+            unescaped.push(c);
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), c) => unescaped.push(c),
+            (_, '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => unescaped.push(c),
+            (None, '"' | '\'') => quote = Some(c),
+            (None, c) => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+// Escapes whitespace, quotes, and the `!` of history references.
+fn escape(path: &str) -> String {
+    let mut escaped = String::new();
+    for c in path.chars() {
+        if c.is_whitespace() || "\\\"'!".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+// Replaces a leading `~` with the home directory.
+fn expand_home(path: &str, home: Option<PathBuf>) -> PathBuf {
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_under_cursor() {
+        let line = r#"load my\ file "a b" 'c\' d"#;
+        assert_eq!(words(line), vec![0..4, 5..13, 14..19, 20..24, 25..26]);
+        assert_eq!(word_at(line, 0), 0..4);
+        assert_eq!(word_at(line, 4), 0..4);
+        assert_eq!(word_at(line, 9), 5..13);
+        assert_eq!(word_at("load  x", 5), 5..5);
+
+        assert_eq!(unescape(r"my\ file"), "my file");
+        assert_eq!(unescape(r#""a b"'c\'"#), r"a bc\");
+        assert_eq!(escape(r#"a b"c"#), r#"a\ b\"c"#);
+        assert_eq!(escape("a!b$c"), r"a\!b$c");
+        assert_eq!(unescape(r"a\!b$c"), "a!b$c");
+    }
+
+    #[test]
+    fn home_expansion() {
+        let home = Some(PathBuf::from("/home/user"));
+        assert_eq!(
+            expand_home("~/", home.clone()),
+            PathBuf::from("/home/user/")
+        );
+        assert_eq!(
+            expand_home("~/src/", home.clone()),
+            PathBuf::from("/home/user/src/")
+        );
+        assert_eq!(
+            expand_home("~other/", home.clone()),
+            PathBuf::from("~other/")
+        );
+        assert_eq!(expand_home("src/", home), PathBuf::from("src/"));
+        assert_eq!(expand_home("~/", None), PathBuf::from("~/"));
+    }
+
+    #[test]
+    fn paths() {
+        let dir = env::temp_dir().join(format!("caprice_paths_{}", std::process::id()));
+        fs::create_dir_all(dir.join("alpha")).unwrap();
+        fs::write(dir.join("a file.txt"), "").unwrap();
+        fs::write(dir.join(".hidden"), "").unwrap();
+        let dir_name = format!("{}/", escape(dir.to_str().unwrap()));
+
+        let candidates = |completer: &PathCompleter, typed: &str| {
+            let line = format!("load {}", typed);
+            let completion = completer.complete(&line, line.len()).unwrap();
+            assert_eq!(completion.range, 5..line.len());
+            completion.candidates
+        };
+        let completer = PathCompleter::new();
+        assert_eq!(
+            candidates(&completer, &dir_name),
+            vec![
+                format!(r"{}a\ file.txt", dir_name),
+                format!("{}alpha/", dir_name)
+            ]
+        );
+        // hidden files are completed once the dot is typed, candidates are
+        // matched with the typed path like keywords
+        assert!(candidates(&completer, &format!("{}.h", dir_name))
+            .contains(&format!("{}.hidden", dir_name)));
+        assert_eq!(
+            candidates(&completer.clone().show_hidden(), &dir_name).len(),
+            3
+        );
+        // quoted paths are completed within the same quotes
+        let quoted = format!("'{}/a f", dir.to_str().unwrap());
+        assert_eq!(
+            candidates(&completer, &quoted),
+            vec![
+                format!("'{}/a file.txt'", dir.to_str().unwrap()),
+                format!("'{}/alpha/", dir.to_str().unwrap())
+            ]
+        );
+        assert!(completer.complete("load /does/not/exist/", 21).is_none());
+
+        // the listing is reused while completing in the same directory
+        fs::write(dir.join("beta"), "").unwrap();
+        assert_eq!(candidates(&completer, &dir_name).len(), 2);
+        assert_eq!(candidates(&PathCompleter::new(), &dir_name).len(), 3);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
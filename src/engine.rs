@@ -1,10 +1,20 @@
+use std::collections::HashMap;
 use std::io::stdout;
+use std::ops::Range;
 
-use crate::autocomplete::Autocomplete;
+use crate::autocomplete::{self, Autocomplete};
+use crate::caprice::{CapriceInput, SecretEcho};
+use crate::completion::{self, Completer, Completion};
 use crate::error::Result;
+use crate::history::{Expansion, History};
+use crate::keymap::Action;
 use crate::scanner::{Scanner, TokenType};
+use crate::search::{Search, SearchEvent};
+use crate::secret::{Secret, SecretEvent};
 use crate::terminal::Terminal;
 use crate::theme::{Theme, DEFAULT_THEME};
+use crate::unicode;
+use crossterm::event::Event;
 use crossterm::execute;
 use crossterm::style::{Attribute, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
 use regex::Regex;
@@ -13,12 +23,28 @@ use regex::Regex;
 pub(crate) struct Executor {
     pub(crate) terminal: Terminal,
     pub(crate) scanner: Scanner,
-    autocomplete: Autocomplete,
+    pub(crate) autocomplete: Autocomplete,
+    pub(crate) history: History,
+    pub(crate) history_suggestions: bool,
+    pub(crate) history_expansion: bool,
     keywords: Vec<String>,
+    // Candidates completing the arguments of each keyword.
+    keyword_args: HashMap<String, Vec<String>>,
+    pub(crate) completer: Option<Box<dyn Completer>>,
+    // Help strings shown next to the keywords they describe in the
+    // suggestion list.
+    descriptions: HashMap<String, String>,
+    // Completers of keyword arguments, by keyword and argument index.
+    arg_completers: HashMap<(String, usize), Box<dyn Completer>>,
     commands: Vec<String>,
     pub(crate) prompt: &'static str,
+    pub(crate) continuation_prompt: &'static str,
     pub(crate) theme: Theme,
     pub(crate) alternate_screen: bool,
+    // Row of the cursor, and its column counted from the start of the input,
+    // which may span several rows of the terminal.
+    cursor_row: usize,
+    cursor_column: usize,
 }
 
 impl Executor {
@@ -27,32 +53,96 @@ impl Executor {
             terminal: Terminal::new(),
             scanner: Scanner::new(),
             autocomplete: Autocomplete::new(),
+            history: History::new(),
+            history_suggestions: false,
+            history_expansion: true,
             keywords: Vec::new(),
-            commands: vec!["/list".to_owned()],
+            keyword_args: HashMap::new(),
+            completer: None,
+            descriptions: HashMap::new(),
+            arg_completers: HashMap::new(),
+            commands: vec!["/list".to_owned(), "/history".to_owned()],
             prompt: "!:",
+            continuation_prompt: "..",
             theme: DEFAULT_THEME,
             alternate_screen: false,
+            cursor_row: 0,
+            cursor_column: 0,
         }
     }
 
     fn print_prompt(&self) -> Result<()> {
+        if let Some(mode) = self.scanner.vi_mode() {
+            print!("{} ", mode.indicator());
+        }
         Ok(execute!(
             stdout(),
             SetForegroundColor(self.theme.prompt_color),
-            Print(self.prompt),
+            Print(self.current_prompt()),
             Print(" "),
             ResetColor,
         )?)
     }
 
     // Block until the next key event.
-    pub(crate) fn get_next_key_event(&mut self) -> Result<Option<String>> {
+    pub(crate) fn get_next_key_event(&mut self) -> Result<Option<CapriceInput>> {
+        // Lines of a paste are submitted one at a time
+        if let Some(token_type) = self.scanner.scan_pasted_line() {
+            return self.exec(token_type);
+        }
+
         self.terminal.flush()?;
-        match self.scanner.scan(self.terminal.next_key_event()?) {
+        let event = self.terminal.next_key_event()?;
+        self.exec_event(event)
+    }
+
+    fn exec_event(&mut self, event: Event) -> Result<Option<CapriceInput>> {
+        // Moving right or to the end of the input accepts the history
+        // suggestion
+        if !self.autocomplete.tabbed && is_input(&event) {
+            let action = self.scanner.action(&event);
+            if matches!(action, Some(Action::ForwardChar | Action::EndOfLine)) {
+                if let Some(hint) = self.history_hint() {
+                    let line = format!("{}{}", self.scanner.buffer(), hint);
+                    self.scanner.update_buffer(line);
+                    self.update_autocomplete(false);
+                    self.refresh_line()?;
+                    return Ok(None);
+                }
+            }
+        }
+
+        // Any key other than the one completing, or a paste, accepts the
+        // highlighted tab suggestion before being handled. Accepting the line
+        // only accepts the suggestion.
+        if self.autocomplete.tabbed && is_input(&event) {
+            let action = self.scanner.action(&event);
+            if action != Some(&Action::Complete) {
+                let accept_line = action == Some(&Action::AcceptLine);
+                self.accept_tabbed()?;
+                if accept_line {
+                    return Ok(None);
+                }
+            }
+        }
+
+        let token_type = self.scanner.scan(event);
+        self.exec(token_type)
+    }
+
+    fn exec(&mut self, token_type: TokenType) -> Result<Option<CapriceInput>> {
+        match token_type {
             TokenType::Token(token) => self.exec_token(token),
-            TokenType::BackSpace => self.exec_backspace(),
-            TokenType::Tab(buffer) => self.exec_tab(buffer),
-            TokenType::Continue(buffer) => self.exec_valid_char(buffer),
+            TokenType::Tab => self.exec_tab(),
+            TokenType::Continue => self.exec_valid_char(),
+            TokenType::Newline => self.exec_newline(),
+            TokenType::Cursor => self.exec_cursor(),
+            TokenType::Emit(token) => self.exec_emit(token),
+            TokenType::Eof => self.exec_eof(),
+            TokenType::PreviousHistory => self.exec_history(true),
+            TokenType::NextHistory => self.exec_history(false),
+            TokenType::SearchHistory => self.exec_search(),
+            TokenType::Resize => self.exec_resize(),
             TokenType::Exit => self.exec_exit(),
             TokenType::None => Ok(None),
         }
@@ -65,9 +155,43 @@ impl Executor {
         self.keywords = valid_keywords.into_iter().map(|k| k.to_owned()).collect()
     }
 
+    pub(crate) fn set_keyword_args(
+        &mut self,
+        keyword: &'static str,
+        args: impl IntoIterator<Item = &'static str>,
+    ) {
+        let args = args
+            .into_iter()
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| arg.to_owned())
+            .collect();
+        self.keyword_args.insert(keyword.to_owned(), args);
+    }
+
+    pub(crate) fn set_keyword_description(
+        &mut self,
+        keyword: &'static str,
+        description: &'static str,
+    ) {
+        self.descriptions
+            .insert(keyword.to_owned(), description.to_owned());
+    }
+
+    pub(crate) fn set_keyword_arg_completer(
+        &mut self,
+        keyword: &'static str,
+        index: usize,
+        completer: Box<dyn Completer>,
+    ) {
+        self.arg_completers
+            .insert((keyword.to_owned(), index), completer);
+    }
+
+    // Prints the prompt on the current line, followed by any input not yet
+    // submitted.
     pub(crate) fn reset_prompt(&mut self) -> Result<()> {
-        self.print_prompt()?;
-        self.clear_prompt()
+        self.autocomplete.reset_tabbed();
+        self.refresh_line()
     }
 
     pub(crate) fn clear_prompt(&mut self) -> Result<()> {
@@ -76,187 +200,628 @@ impl Executor {
         Ok(())
     }
 
-    fn exec_token(&mut self, token: String) -> Result<Option<String>> {
-        // if tab suggestions are active then we stop it
-        if let Some(buffer) = self.autocomplete.get_current_tabbed_autocomplete() {
-            self.terminal.clear_from_cursor()?;
-            self.autocomplete.reset_tabbed();
-            self.scanner.update_buffer(buffer);
-            return Ok(None);
-        }
+    fn exec_token(&mut self, token: String) -> Result<Option<CapriceInput>> {
+        // Leave the whole input line on screen
+        self.finish_line()?;
 
         // We've committed to this input, clear the scanner
         self.scanner.clear_buffer();
+        self.autocomplete.update("", &self.keywords);
 
-        if self.keywords.contains(&token)
-            || self
-                .keywords
-                .contains(&token.split(' ').next().unwrap_or_default().to_string())
-        {
-            self.terminal.goto_next_line()?;
+        // Echo the line with its history references expanded
+        let expansion = if self.history_expansion {
+            self.history.expand(&token)
+        } else {
+            Expansion::Unchanged
+        };
+        let token = match expansion {
+            Expansion::Unchanged => token,
+            Expansion::Expanded(expanded) => {
+                self.print_msg(&expanded)?;
+                expanded
+            }
+            Expansion::NotFound(reference) => {
+                self.print_msg(&format!("{}: event not found", reference))?;
+                self.reset_prompt()?;
+                return Ok(None);
+            }
+        };
+        // Persisting the history is best effort, the line is still
+        // recalled during the session
+        if let Err(e) = self.history.add(&token) {
+            self.print_msg(&e.to_string())?;
+        }
+
+        // Keywords are sent with their own casing
+        let first = token.split_whitespace().next().unwrap_or_default();
+        let keyword = self
+            .canonical_keyword(&token)
+            .or_else(|| {
+                self.canonical_keyword(first)
+                    .map(|keyword| token.replacen(first, &keyword, 1))
+            })
+            .or_else(|| {
+                self.completer
+                    .as_ref()
+                    .filter(|completer| completer.is_keyword(first))
+                    .map(|_| token.clone())
+            });
+        if let Some(token) = keyword {
             self.clear_prompt()?;
-            return Ok(Some(token));
-        } else if self.commands.contains(&token) {
+            return Ok(Some(CapriceInput::Keyword(token)));
+        } else if self
+            .commands
+            .iter()
+            .any(|command| token.split_whitespace().next() == Some(command.as_str()))
+        {
             self.exec_command(token)?;
-            self.terminal.goto_beginning_of_line()?;
             self.reset_prompt()?;
         } else {
-            self.terminal.goto_next_line()?;
             self.reset_prompt()?;
         }
 
         Ok(None)
     }
 
+    // Moves on to the next line of a multi-line input.
+    fn exec_newline(&mut self) -> Result<Option<CapriceInput>> {
+        self.finish_line()?;
+        self.scanner.continue_input();
+        self.autocomplete.update("", &self.keywords);
+        self.reset_prompt()?;
+        Ok(None)
+    }
+
+    // Replaces the input with an older or newer history entry. The line
+    // typed before navigating the history is restored past the newest entry.
+    fn exec_history(&mut self, previous: bool) -> Result<Option<CapriceInput>> {
+        let line = if previous {
+            self.history.previous(self.scanner.buffer())
+        } else {
+            self.history.next()
+        };
+        if let Some(line) = line {
+            self.scanner.recall(line);
+            self.update_autocomplete(false);
+            self.refresh_line()?;
+        }
+        Ok(None)
+    }
+
+    // Searches the history incrementally. Accepting the line places the
+    // match on the input line, cancelling the search restores the input.
+    fn exec_search(&mut self) -> Result<Option<CapriceInput>> {
+        let mut search = Search::new();
+        loop {
+            self.print_search(&search)?;
+            self.terminal.flush()?;
+
+            let event = self.terminal.next_key_event()?;
+            if let Event::Resize(..) = event {
+                self.cursor_row = self.cursor_column / self.columns();
+            }
+            match search.scan(&event, &self.scanner.keymap, &self.history) {
+                SearchEvent::Accept | SearchEvent::Cancel => break,
+                SearchEvent::None => (),
+            }
+        }
+
+        if let Some(line) = search.found.and_then(|idx| self.history.get(idx)) {
+            self.scanner.recall(line);
+            self.history.reset();
+        }
+        self.update_autocomplete(false);
+        self.reset_prompt()?;
+        Ok(None)
+    }
+
+    // Prints the search query followed by the matching history entry, the
+    // matched text highlighted, placing the cursor at the match.
+    fn print_search(&mut self, search: &Search) -> Result<()> {
+        let label = format!(
+            "({}reverse-i-search)'{}': ",
+            if search.failing { "failing " } else { "" },
+            search.query
+        );
+        let entry = search
+            .found
+            .and_then(|idx| self.history.get(idx))
+            .unwrap_or_default()
+            .replace('\n', " ");
+        let (before, matched, after) = match entry.rfind(&search.query) {
+            Some(start) if !search.query.is_empty() => {
+                let end = start + search.query.len();
+                (&entry[..start], &entry[start..end], &entry[end..])
+            }
+            _ => (entry.as_str(), "", ""),
+        };
+
+        self.goto_input_start()?;
+        self.terminal.clear_from_cursor()?;
+        execute!(
+            stdout(),
+            SetForegroundColor(self.theme.prompt_color),
+            Print(&label),
+            ResetColor,
+            Print(before),
+            SetBackgroundColor(self.theme.suggestion_bg),
+            SetForegroundColor(self.theme.suggestion_fg),
+            Print(matched),
+            ResetColor,
+            Print(after),
+        )?;
+        let end = unicode::width(&label) + unicode::width(&entry);
+        let cursor = unicode::width(&label) + unicode::width(before);
+        self.place_cursor(end, cursor)
+    }
+
+    // Signals the end of input to the host application.
+    fn exec_eof(&mut self) -> Result<Option<CapriceInput>> {
+        self.finish_line()?;
+        self.clear_prompt()?;
+        Ok(Some(CapriceInput::Eof))
+    }
+
+    // Sends a token bound to a key to the host application, keeping the
+    // current line for the next prompt.
+    fn exec_emit(&mut self, token: String) -> Result<Option<CapriceInput>> {
+        self.finish_line()?;
+        self.autocomplete.reset_tabbed();
+        Ok(Some(CapriceInput::Keyword(token)))
+    }
+
     fn exec_command(&mut self, command: String) -> Result<()> {
-        if command == "/list" {
-            self.terminal.goto_next_line()?;
-            for token in self.keywords.iter() {
-                print!("{}", token);
-                self.terminal.goto_next_line()?;
+        let mut args = command.split_whitespace();
+        match args.next() {
+            Some("/list") => {
+                for token in self.keywords.iter() {
+                    print!("{}", token);
+                    self.terminal.goto_next_line()?;
+                }
             }
+            Some("/history") => self.exec_history_command(args.collect())?,
+            _ => (),
         }
 
         self.autocomplete.reset_tabbed();
         Ok(())
     }
 
-    pub(crate) fn exec_exit(&mut self) -> Result<Option<String>> {
+    // `/history [N] [pattern]` prints the last N entries containing the
+    // pattern, `/history clear` clears the history.
+    fn exec_history_command(&mut self, args: Vec<&str>) -> Result<()> {
+        if args == ["clear"] {
+            if let Err(e) = self.history.clear() {
+                self.print_msg(&e.to_string())?;
+            }
+            return Ok(());
+        }
+        let (count, pattern) = match args.split_first() {
+            Some((first, rest)) => match first.parse::<usize>() {
+                Ok(count) => (Some(count), rest.join(" ")),
+                Err(_) => (None, args.join(" ")),
+            },
+            None => (None, String::new()),
+        };
+        for (number, entry) in self.history.recent(count, &pattern) {
+            print!("{:>5}  {}", number, entry.replace('\n', " "));
+            self.terminal.goto_next_line()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn exec_exit(&mut self) -> Result<Option<CapriceInput>> {
         self.terminal.clear_from_cursor()?;
         self.terminal.flush()?;
         self.terminal.exit();
         Ok(None)
     }
 
-    fn exec_backspace(&mut self) -> Result<Option<String>> {
-        if let Some(buffer) = self.autocomplete.get_current_tabbed_autocomplete() {
-            let mut updated_buffer = buffer;
-            updated_buffer.pop();
-            self.scanner.update_buffer(updated_buffer);
+    fn exec_cursor(&mut self) -> Result<Option<CapriceInput>> {
+        self.update_autocomplete(false);
+        self.refresh_line()?;
+        Ok(None)
+    }
+
+    // Returns the keyword matching the word, preferring an exact match when
+    // casing is ignored.
+    fn canonical_keyword(&self, word: &str) -> Option<String> {
+        if word.is_empty() {
+            return None;
+        }
+        let ignore_case = self.autocomplete.matching.ignores_case(word);
+        self.keywords
+            .iter()
+            .find(|keyword| keyword.as_str() == word)
+            .or_else(|| {
+                self.keywords.iter().find(|keyword| {
+                    ignore_case && autocomplete::strip_prefix(keyword, word, true) == Some("")
+                })
+            })
+            .cloned()
+    }
+
+    // Byte range of the input replaced by a suggestion and the candidates
+    // completing it. Unless the completer provides them, the range is the
+    // word under the cursor, completed with the keywords for the first word
+    // and the arguments of the keyword for the following ones. Candidates
+    // are matched against the part of the range before the cursor.
+    fn completion(&self) -> (Range<usize>, Vec<String>) {
+        if let Some(completion) = self.complete_with(self.completer.as_deref()) {
+            return completion;
+        }
+
+        let buffer = self.scanner.buffer();
+        let range = completion::word_at(buffer, self.scanner.cursor());
+        // position of the word on the line, the keyword being the first
+        let position = completion::words(buffer)
+            .iter()
+            .filter(|word| word.start < range.start)
+            .count();
+        if position == 0 {
+            return (range, self.keywords.clone());
+        }
+
+        let first = buffer.split_whitespace().next().unwrap_or_default();
+        let keyword = match self.canonical_keyword(first) {
+            Some(keyword) => keyword,
+            None => return (range, Vec::new()),
+        };
+        let completer = self.arg_completers.get(&(keyword.clone(), position - 1));
+        if let Some(completion) = self.complete_with(completer.map(|completer| completer.as_ref()))
+        {
+            return completion;
+        }
+        let candidates = self.keyword_args.get(&keyword).cloned().unwrap_or_default();
+        (range, candidates)
+    }
+
+    // Candidates of the completer for the input, ignoring ranges not
+    // containing the cursor or splitting a char.
+    fn complete_with(
+        &self,
+        completer: Option<&dyn Completer>,
+    ) -> Option<(Range<usize>, Vec<String>)> {
+        let buffer = self.scanner.buffer();
+        let cursor = self.scanner.cursor();
+        let Completion { range, candidates } = completer?.complete(buffer, cursor)?;
+        if range.start <= cursor && cursor <= range.end && buffer.get(range.clone()).is_some() {
+            Some((range, candidates))
+        } else {
+            None
         }
+    }
 
-        self.terminal.backspace()?;
+    // Updates the suggestions for the word under the cursor. Completing an
+    // argument not typed yet lists all its candidates.
+    fn update_autocomplete(&mut self, list: bool) {
+        let (range, candidates) = self.completion();
+        let word = &self.scanner.buffer()[range.start..self.scanner.cursor()];
+        if list && word.is_empty() && range.start > 0 {
+            self.autocomplete.list(&candidates);
+        } else {
+            self.autocomplete.update(word, &candidates);
+        }
+        self.autocomplete.range = range;
+    }
 
+    // Replaces the word under the cursor with the highlighted tab suggestion
+    // and clears the suggestion list.
+    fn accept_tabbed(&mut self) -> Result<()> {
+        if let Some(suggestion) = self.autocomplete.get_current_tabbed_autocomplete() {
+            let (line, cursor) = self
+                .autocomplete
+                .complete(self.scanner.buffer(), &suggestion);
+            self.scanner.complete(line, cursor);
+        }
+        self.update_autocomplete(false);
         self.autocomplete.reset_tabbed();
-        self.terminal.clear_from_cursor()?;
-        Ok(None)
+        self.refresh_line()
     }
 
-    fn exec_tab(&mut self, buffer: String) -> Result<Option<String>> {
+    fn exec_tab(&mut self) -> Result<Option<CapriceInput>> {
         // set autocompleted state
         self.autocomplete.tabbed = true;
 
         // update the autocompleted state
-        self.autocomplete.update(&buffer, &self.keywords);
+        self.update_autocomplete(true);
 
         // return if there are no autocomplete suggestions
-        if self.autocomplete.get_common().is_empty() {
+        if self.autocomplete.get_keywords().is_empty() {
             Ok(None)
         } else {
-            // print autocomplete suggestions
+            // select the next suggestion and print them all
+            self.autocomplete.incr_idx();
             self.print_autocomplete_suggestions()?;
             Ok(None)
         }
     }
 
+    // Redraws the input line, and the suggestion list if visible, laid out
+    // for the new terminal size.
+    fn exec_resize(&mut self) -> Result<Option<CapriceInput>> {
+        // the terminal reflows the rows of the input to its new width
+        self.cursor_row = self.cursor_column / self.columns();
+        if self.autocomplete.tabbed && !self.autocomplete.get_keywords().is_empty() {
+            self.print_autocomplete_suggestions()?;
+        } else {
+            self.refresh_line()?;
+        }
+        Ok(None)
+    }
+
     fn print_autocomplete_suggestions(&mut self) -> Result<()> {
         // a margin left on the right of the terminal
         let word_margin = 1;
         // spaces between each printed suggestion
-        let word_separation: u16 = 2;
-
-        let mut num_per_line: u16;
+        let word_separation = 2;
 
         self.autocomplete.amortize();
 
-        self.autocomplete.incr_idx();
+        let columns = self.columns();
 
-        // get num of words that fit in one line
-        if let Some(first) = self.autocomplete.get_keywords().first() {
-            num_per_line = self.terminal.size().0 / (first.len() as u16 + word_separation);
-            if num_per_line > word_margin {
-                num_per_line -= word_margin;
-            }
-        } else {
-            num_per_line = 0;
+        // get num of words that fit in one line, at least one even if the
+        // terminal is narrower than a word
+        let mut num_per_line = match self.autocomplete.get_keywords().first() {
+            Some(first) => columns / (unicode::width(first) + word_separation),
+            None => 0,
+        };
+        if num_per_line > word_margin {
+            num_per_line -= word_margin;
         }
+        // described suggestions are listed one per row
+        let described = self
+            .autocomplete
+            .get_keywords()
+            .iter()
+            .any(|keyword| self.descriptions.contains_key(keyword.trim_end()));
+        let num_per_line = if described { 1 } else { num_per_line.max(1) };
 
-        // get vertical distance of current line to end of terminal
-        let distance_to_end = self.terminal.size().1 - (self.terminal.get_cursor_pos().1 + 1);
-
-        // get required number of lines to print autocomplete suggestions
-        let needed_lines =
-            (self.autocomplete.get_keywords().len() as f32 / (num_per_line) as f32).ceil() as u16;
+        // the input line shows the highlighted suggestion in place of the
+        // word under the cursor
+        let idx = self.autocomplete.tab_idx;
+        let selected = self
+            .autocomplete
+            .get_keywords()
+            .get(idx)
+            .map(|keyword| keyword.trim_end().to_owned())
+            .unwrap_or_default();
+        let (line, cursor) = self.autocomplete.complete(self.scanner.buffer(), &selected);
+        self.goto_input_start()?;
+        self.terminal.clear_from_cursor()?;
+        self.print_prompt()?;
+        print!("{}", line);
+        let end = self.prompt_width() + unicode::width(&line);
+        let cursor = self.prompt_width() + unicode::width(&line[..cursor]);
+        self.place_cursor(end, end)?;
 
-        // if we need space to display the suggestions, scroll the terminal up
-        if distance_to_end < needed_lines {
-            self.terminal.scroll_up(needed_lines - distance_to_end)?;
+        // print the suggestions below the input, counting the rows they
+        // take as long suggestions may wrap
+        let mut rows = 0;
+        for (row, words) in self
+            .autocomplete
+            .get_keywords()
+            .chunks(num_per_line)
+            .enumerate()
+        {
+            print!("\r\n");
+            let mut width = 0;
+            for (i, word) in words.iter().enumerate() {
+                // highlight current selection
+                if row * num_per_line + i == idx {
+                    print!(
+                        "{}{}{}  {}",
+                        SetBackgroundColor(self.theme.suggestion_bg),
+                        SetForegroundColor(self.theme.suggestion_fg),
+                        word,
+                        Attribute::Reset
+                    );
+                } else {
+                    print!("{}  ", word);
+                }
+                width += unicode::width(word) + word_separation;
+            }
+            if let Some(description) = words
+                .first()
+                .filter(|_| described)
+                .and_then(|word| self.descriptions.get(word.trim_end()))
+            {
+                // cut the description to fit the row
+                let available = columns.saturating_sub(width + 3 + word_margin);
+                let description = unicode::truncate(description, available);
+                if !description.is_empty() {
+                    print!("-- {}", description);
+                    width += 3 + unicode::width(description);
+                }
+            }
+            rows += end_row(width, columns) + 1;
         }
 
-        self.terminal.goto_beginning_of_line()?;
-        self.terminal.save_cursor()?;
-        self.terminal.goto_next_line()?;
+        // return to the cursor on the input line
+        self.terminal
+            .move_up(rows + self.cursor_row - cursor / columns)?;
+        self.cursor_row = cursor / columns;
+        self.cursor_column = cursor;
+        self.terminal.goto_column((cursor % columns) as u16)
+    }
 
-        let idx = self.autocomplete.tab_idx;
+    fn exec_valid_char(&mut self) -> Result<Option<CapriceInput>> {
+        self.update_autocomplete(false);
+        self.autocomplete.reset_tabbed();
+        self.refresh_line()?;
+        Ok(None)
+    }
 
-        let mut count: u16 = 0;
-
-        for (i, word) in self.autocomplete.get_keywords().iter().enumerate() {
-            // highlight current selection
-            if i == idx {
-                print!(
-                    "{}{}{}  {}",
-                    SetBackgroundColor(self.theme.suggestion_bg),
-                    SetForegroundColor(self.theme.suggestion_fg),
-                    word,
-                    Attribute::Reset
-                );
-            } else {
-                print!("{}  ", word);
-            }
-            count += 1;
-            if count == num_per_line {
-                self.terminal.goto_next_line()?;
-                count = 0;
-            }
+    // Redraws the prompt and the input buffer with the autocomplete hint,
+    // then places the cursor at its position within the buffer.
+    fn refresh_line(&mut self) -> Result<()> {
+        self.goto_input_start()?;
+        self.terminal.clear_from_cursor()?;
+        self.print_prompt()?;
+
+        let buffer = self.scanner.buffer();
+        let (hint, at) = self.hint();
+        print!(
+            "{}{}{}{}{}",
+            &buffer[..at],
+            SetForegroundColor(self.theme.autocomplete_color),
+            hint,
+            Attribute::Reset,
+            &buffer[at..]
+        );
+        let end = self.prompt_width() + unicode::width(buffer) + unicode::width(hint);
+        let cursor = self.prompt_width() + unicode::width(&buffer[..self.scanner.cursor()]);
+        self.place_cursor(end, cursor)
+    }
+
+    // The hint shown on the input line and the byte offset of the buffer it
+    // is shown at. The autocomplete hint follows the word under the cursor,
+    // the history hint the end of the input.
+    fn hint(&self) -> (&str, usize) {
+        let buffer = self.scanner.buffer();
+        if let Some(hint) = self.history_hint() {
+            return (hint, buffer.len());
         }
-        self.terminal.restore_cursor()?;
-        self.terminal.goto_beginning_of_line()?;
+        match self.autocomplete.hint(buffer) {
+            "" => ("", buffer.len()),
+            hint => (hint, self.autocomplete.range.end),
+        }
+    }
 
-        if let Some(keyword) = self.autocomplete.get_keywords().get(idx) {
-            let keyword = keyword.trim_end();
-            self.print_prompt()?;
-            print!("{}", keyword);
-        };
+    // Redraws the prompt and the input buffer without the autocomplete hint,
+    // then moves to the start of the row below the input.
+    fn finish_line(&mut self) -> Result<()> {
+        self.goto_input_start()?;
+        self.terminal.clear_from_cursor()?;
+        self.print_prompt()?;
+        print!("{}\r\n", self.scanner.buffer());
         Ok(())
     }
 
-    fn exec_valid_char(&mut self, buffer: String) -> Result<Option<String>> {
-        let origin_buffer_char = buffer.clone().pop();
+    // The rest of the most recent history entry extending the input, when
+    // history suggestions are enabled and the cursor is at the end of a
+    // single line input.
+    fn history_hint(&self) -> Option<&str> {
+        let buffer = self.scanner.buffer();
+        if !self.history_suggestions
+            || self.scanner.cursor() != buffer.len()
+            || self.scanner.is_continuation()
+        {
+            return None;
+        }
+        self.history
+            .suggest(buffer)
+            .map(|entry| &entry[buffer.len()..])
+    }
 
-        if let Some(buffer) = self.autocomplete.get_current_tabbed_autocomplete() {
-            if origin_buffer_char.is_some() {
-                self.scanner
-                    .update_buffer(format!("{}{}", buffer, origin_buffer_char.unwrap()));
-            } else {
-                self.scanner.update_buffer(buffer);
-            }
+    // Moves the cursor to the start of the first row of the input.
+    fn goto_input_start(&mut self) -> Result<()> {
+        self.terminal.move_up(self.cursor_row)?;
+        self.cursor_row = 0;
+        self.cursor_column = 0;
+        self.terminal.goto_column(0)
+    }
+
+    // Moves the cursor from the end of the printed input to the target
+    // column, both counted from the start of the input.
+    fn place_cursor(&mut self, end: usize, target: usize) -> Result<()> {
+        let columns = self.columns();
+        let end_row = end_row(end, columns);
+        let target_row = target / columns;
+        if end_row > target_row {
+            self.terminal.move_up(end_row - target_row)?;
+        } else {
+            // the target starts a row which is not on screen yet
+            self.terminal.move_down(target_row - end_row)?;
         }
+        self.cursor_row = target_row;
+        self.cursor_column = target;
+        self.terminal.goto_column((target % columns) as u16)
+    }
 
-        if origin_buffer_char.is_some() {
-            print!("{}", origin_buffer_char.unwrap());
+    // Width of the terminal, input never wraps if it can't be queried.
+    fn columns(&self) -> usize {
+        match self.terminal.size().0 {
+            0 => usize::MAX,
+            columns => columns as usize,
         }
+    }
 
-        self.autocomplete.update(&buffer, &self.keywords);
+    // Width of the prompt, including the vi mode indicator and the separating spaces.
+    fn prompt_width(&self) -> usize {
+        let indicator_width = match self.scanner.vi_mode() {
+            Some(mode) => unicode::width(mode.indicator()) + 1,
+            None => 0,
+        };
+        indicator_width + unicode::width(self.current_prompt()) + 1
+    }
 
-        self.autocomplete.print_same_line_autocompleted(
-            self.theme.autocomplete_color,
-            &buffer,
-            &self.terminal,
-        )?;
+    fn current_prompt(&self) -> &'static str {
+        if self.scanner.is_continuation() {
+            self.continuation_prompt
+        } else {
+            self.prompt
+        }
+    }
 
-        self.autocomplete.reset_tabbed();
-        Ok(None)
+    // Reads a secret on a new line below the input, echoing a mask or
+    // nothing, then redraws the prompt and the line being edited below it.
+    pub(crate) fn read_secret(&mut self, prompt: &str, echo: SecretEcho) -> Result<Option<String>> {
+        self.leave_input_line()?;
+        let mut secret = Secret::new();
+        let value = loop {
+            self.goto_input_start()?;
+            self.terminal.clear_from_cursor()?;
+            execute!(
+                stdout(),
+                SetForegroundColor(self.theme.prompt_color),
+                Print(prompt),
+                Print(" "),
+                ResetColor,
+            )?;
+            let mask = match echo {
+                SecretEcho::Mask => secret.len(),
+                SecretEcho::Hidden => 0,
+            };
+            print!("{}", "*".repeat(mask));
+            let end = unicode::width(prompt) + 1 + mask;
+            self.place_cursor(end, end)?;
+            self.terminal.flush()?;
+
+            let event = self.terminal.next_key_event()?;
+            if let Event::Resize(..) = event {
+                self.cursor_row = self.cursor_column / self.columns();
+            }
+            match secret.scan(&event) {
+                SecretEvent::Submit => break Some(secret.into_inner()),
+                SecretEvent::Cancel => break None,
+                SecretEvent::Edited | SecretEvent::None => (),
+            }
+        };
+        self.terminal.goto_next_line()?;
+        self.cursor_row = 0;
+        self.cursor_column = 0;
+        self.reset_prompt()?;
+        Ok(value)
+    }
+
+    // Moves to the start of the row below the input when it is on screen,
+    // leaving it there. Once an input is finished, the cursor is already at
+    // the start of an empty row.
+    fn leave_input_line(&mut self) -> Result<()> {
+        if self.cursor_column > 0 {
+            self.finish_line()?;
+        }
+        Ok(())
+    }
+
+    // Prints the message in place of the input line, then redraws the
+    // prompt and the input below it.
+    pub(crate) fn print_above_input(&mut self, msg: &str) -> Result<()> {
+        self.goto_input_start()?;
+        self.clear_prompt()?;
+        self.print_msg(msg)?;
+        self.reset_prompt()
     }
 
     pub fn print_msg(&mut self, msg: &str) -> Result<()> {
@@ -267,6 +832,21 @@ impl Executor {
     }
 }
 
+// Row, counted from the start of the input, on which the terminal leaves the
+// cursor after printing `width` columns. The cursor stays on the last column
+// of a full row until the next character is printed.
+fn end_row(width: usize, columns: usize) -> usize {
+    if width > 0 && width.is_multiple_of(columns) {
+        width / columns - 1
+    } else {
+        width / columns
+    }
+}
+
+fn is_input(event: &Event) -> bool {
+    matches!(event, Event::Key(_) | Event::Paste(_))
+}
+
 fn get_valid_keywords(keywords: Vec<&'static str>) -> Vec<&'static str> {
     let re = &Regex::new(r"^[_a-zA-Z][A-Za-z_0-9]*$").unwrap();
     keywords
@@ -279,6 +859,188 @@ fn get_valid_keywords(keywords: Vec<&'static str>) -> Vec<&'static str> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn no_hint_after_submit() {
+        let mut executor = Executor::new();
+        executor.set_keywords(vec!["some_token"]);
+        executor.autocomplete.update("some", &executor.keywords);
+        assert_eq!(executor.autocomplete.get_common(), "some_token");
+
+        // the new prompt is drawn without the hint of the submitted line
+        executor.exec_token("some".to_owned()).unwrap();
+        assert_eq!(executor.autocomplete.get_common(), "");
+    }
+
+    #[test]
+    fn paste_accepts_tab_suggestion() {
+        let mut executor = Executor::new();
+        executor.set_keywords(vec!["color", "exit"]);
+        executor.scanner.update_buffer("co".to_owned());
+        executor.update_autocomplete(false);
+        executor.exec_tab().unwrap();
+
+        executor
+            .exec_event(Event::Paste(" red".to_owned()))
+            .unwrap();
+        assert_eq!(executor.scanner.buffer(), "color red");
+        assert!(!executor.autocomplete.tabbed);
+    }
+
+    #[test]
+    fn wrapped_rows() {
+        assert_eq!(end_row(0, 10), 0);
+        assert_eq!(end_row(9, 10), 0);
+        assert_eq!(end_row(10, 10), 0);
+        assert_eq!(end_row(11, 10), 1);
+        assert_eq!(end_row(25, 10), 2);
+        assert_eq!(end_row(25, usize::MAX), 0);
+    }
+
+    #[test]
+    fn argument_completion() {
+        let mut executor = Executor::new();
+        executor.set_keywords(vec!["color", "exit"]);
+        executor.set_keyword_args("color", ["red", "green"]);
+
+        executor.scanner.update_buffer("color gr".to_owned());
+        executor.update_autocomplete(false);
+        assert_eq!(executor.autocomplete.get_keywords(), &vec!["green"]);
+        assert_eq!(executor.autocomplete.hint(executor.scanner.buffer()), "een");
+        assert_eq!(
+            executor
+                .autocomplete
+                .complete(executor.scanner.buffer(), "green"),
+            ("color green".to_owned(), 11)
+        );
+
+        // the word under the cursor is replaced, keeping the rest of the line
+        executor.scanner.complete("cxx red".to_owned(), 1);
+        executor.update_autocomplete(false);
+        assert_eq!(executor.autocomplete.get_keywords(), &vec!["color"]);
+        assert_eq!(
+            executor
+                .autocomplete
+                .complete(executor.scanner.buffer(), "color"),
+            ("color red".to_owned(), 5)
+        );
+
+        // tab lists the candidates of an argument not typed yet
+        executor.scanner.update_buffer("color ".to_owned());
+        executor.update_autocomplete(false);
+        assert!(executor.autocomplete.get_keywords().is_empty());
+        executor.update_autocomplete(true);
+        assert_eq!(executor.autocomplete.get_keywords(), &vec!["red", "green"]);
+
+        executor.scanner.update_buffer("exit ".to_owned());
+        executor.update_autocomplete(true);
+        assert!(executor.autocomplete.get_keywords().is_empty());
+
+        // a completer set for an argument takes precedence at its position
+        executor.set_keyword_arg_completer(
+            "color",
+            1,
+            Box::new(|line: &str, cursor: usize| {
+                Some(Completion::word(line, cursor, vec!["bright".to_owned()]))
+            }),
+        );
+        executor.scanner.update_buffer("color r".to_owned());
+        executor.update_autocomplete(false);
+        assert_eq!(executor.autocomplete.get_keywords(), &vec!["red"]);
+        executor.scanner.update_buffer(r"color red\ x b".to_owned());
+        executor.update_autocomplete(false);
+        assert_eq!(executor.autocomplete.get_keywords(), &vec!["bright"]);
+    }
+
+    #[test]
+    fn hint_within_line() {
+        let mut executor = Executor::new();
+        executor.set_keywords(vec!["color", "exit"]);
+        executor.set_keyword_args("color", ["red", "green"]);
+
+        // the hint follows the word under the cursor, before the rest of the
+        // line
+        executor.scanner.complete("col red".to_owned(), 3);
+        executor.update_autocomplete(false);
+        assert_eq!(executor.hint(), ("or", 3));
+        executor.scanner.complete("color gr x".to_owned(), 8);
+        executor.update_autocomplete(false);
+        assert_eq!(executor.hint(), ("een", 8));
+
+        // the part of the word after the cursor is part of the match
+        executor.scanner.complete("colx red".to_owned(), 2);
+        executor.update_autocomplete(false);
+        assert_eq!(executor.hint(), ("", 8));
+        executor.scanner.complete("col red".to_owned(), 2);
+        executor.update_autocomplete(false);
+        assert_eq!(executor.hint(), ("or", 3));
+    }
+
+    #[test]
+    fn completer() {
+        let mut executor = Executor::new();
+        executor.set_keywords(vec!["connect", "exit"]);
+        executor.completer = Some(Box::new(|line: &str, cursor: usize| {
+            let devices = vec!["dev0".to_owned(), "dev1".to_owned(), "usb".to_owned()];
+            match line.strip_prefix("connect ") {
+                // the devices are completed after a `usb:` prefix
+                Some(arg) if arg.starts_with("usb:") => Some(Completion {
+                    range: 12..cursor.max(12),
+                    candidates: devices,
+                }),
+                Some(_) => Some(Completion::word(line, cursor, devices)),
+                None => None,
+            }
+        }));
+
+        executor.scanner.update_buffer("connect d".to_owned());
+        executor.update_autocomplete(false);
+        assert_eq!(executor.autocomplete.get_keywords(), &vec!["dev0", "dev1"]);
+
+        executor.scanner.update_buffer("connect usb:u".to_owned());
+        executor.update_autocomplete(false);
+        assert_eq!(executor.autocomplete.get_keywords(), &vec!["usb"]);
+        assert_eq!(
+            executor
+                .autocomplete
+                .complete(executor.scanner.buffer(), "usb"),
+            ("connect usb:usb".to_owned(), 15)
+        );
+
+        // without candidates from the completer, keywords are completed
+        executor.scanner.update_buffer("ex".to_owned());
+        executor.update_autocomplete(false);
+        assert_eq!(executor.autocomplete.get_keywords(), &vec!["exit"]);
+    }
+
+    #[test]
+    fn completer_keywords() {
+        struct Devices;
+        impl Completer for Devices {
+            fn complete(&self, _line: &str, _cursor: usize) -> Option<Completion> {
+                None
+            }
+            fn is_keyword(&self, word: &str) -> bool {
+                word == "dev0"
+            }
+        }
+
+        let mut executor = Executor::new();
+        executor.set_keywords(vec!["exit"]);
+        executor.completer = Some(Box::new(completion::PathCompleter::new()));
+        // candidates of the completer are not keywords
+        assert!(executor
+            .exec_token("Cargo.toml".to_owned())
+            .unwrap()
+            .is_none());
+
+        executor.completer = Some(Box::new(Devices));
+        assert!(matches!(
+            executor.exec_token("dev0 up".to_owned()).unwrap(),
+            Some(CapriceInput::Keyword(token)) if token == "dev0 up"
+        ));
+        assert!(executor.exec_token("dev1".to_owned()).unwrap().is_none());
+    }
+
     #[test]
     fn filter_keywords() {
         let empty_keywords: Vec<&'static str> = Vec::new();
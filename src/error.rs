@@ -1,3 +1,4 @@
+use crate::caprice::CapriceInput;
 use std::fmt::Display;
 use std::sync::mpsc;
 
@@ -7,13 +8,15 @@ pub type Result<T> = std::result::Result<T, CapriceError>;
 #[non_exhaustive]
 pub enum CapriceError {
     CrosstermError(crossterm::ErrorKind),
-    SendErr(mpsc::SendError<String>),
+    SendErr(mpsc::SendError<CapriceInput>),
+    HistoryFile(std::io::Error),
 }
 
 impl std::error::Error for CapriceError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             CapriceError::CrosstermError(e) => Some(e),
+            CapriceError::HistoryFile(e) => Some(e),
             _ => None,
         }
     }
@@ -24,6 +27,7 @@ impl Display for CapriceError {
         match self {
             CapriceError::CrosstermError(e) => write!(fmt, "Terminal error occurred: {}", e),
             CapriceError::SendErr(e) => write!(fmt, "Send error occurred: {}", e),
+            CapriceError::HistoryFile(e) => write!(fmt, "History file error occurred: {}", e),
         }
     }
 }
@@ -40,4 +44,4 @@ macro_rules! impl_from {
 }
 
 impl_from!(crossterm::ErrorKind, CapriceError::CrosstermError);
-impl_from!(mpsc::SendError<String>, CapriceError::SendErr);
+impl_from!(mpsc::SendError<CapriceInput>, CapriceError::SendErr);
@@ -0,0 +1,476 @@
+use crate::error::{CapriceError, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// Result of expanding the history references of a line.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Expansion {
+    Unchanged,
+    Expanded(String),
+    // The first reference without a matching entry.
+    NotFound(String),
+}
+
+// Default maximum number of entries kept in the history.
+const HISTORY_SIZE: usize = 1000;
+
+// Lines submitted during the session, oldest first, and the position of the
+// entry currently recalled into the input.
+#[derive(Debug)]
+pub(crate) struct History {
+    entries: Vec<String>,
+    idx: Option<usize>,
+    // The line being typed before navigating the history.
+    draft: String,
+    pub(crate) file: Option<PathBuf>,
+    pub(crate) max_size: usize,
+    pub(crate) ignore_dups: bool,
+    pub(crate) ignore_space: bool,
+}
+
+impl History {
+    pub(crate) fn new() -> Self {
+        History {
+            entries: Vec::new(),
+            idx: None,
+            draft: String::new(),
+            file: None,
+            max_size: HISTORY_SIZE,
+            ignore_dups: false,
+            ignore_space: false,
+        }
+    }
+
+    // Loads the most recent entries of the history file, if any.
+    pub(crate) fn load(&mut self) -> Result<()> {
+        if let Some(path) = &self.file {
+            let mut entries = read_entries(path).map_err(CapriceError::HistoryFile)?;
+            entries.drain(..entries.len().saturating_sub(self.max_size));
+            self.entries = entries;
+        }
+        Ok(())
+    }
+
+    // Adds a submitted line, appending it to the history file if any, and
+    // restarts the navigation from the newest entry.
+    pub(crate) fn add(&mut self, line: &str) -> Result<()> {
+        self.reset();
+        if !self.accepts(line) {
+            return Ok(());
+        }
+        self.entries.push(line.to_owned());
+        self.entries
+            .drain(..self.entries.len().saturating_sub(self.max_size));
+        if let Some(path) = &self.file {
+            append_entry(path, line, self.max_size).map_err(CapriceError::HistoryFile)?;
+        }
+        Ok(())
+    }
+
+    fn accepts(&self, line: &str) -> bool {
+        let blank = line.trim().is_empty();
+        let ignored_space = self.ignore_space && line.starts_with(' ');
+        let duplicate = self.ignore_dups && self.entries.last().map(String::as_str) == Some(line);
+        !blank && !ignored_space && !duplicate
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.idx = None;
+        self.draft.clear();
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> Option<&str> {
+        self.entries.get(idx).map(String::as_str)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Most recent single line entry extending the prefix.
+    pub(crate) fn suggest(&self, prefix: &str) -> Option<&str> {
+        if prefix.is_empty() {
+            return None;
+        }
+        self.entries
+            .iter()
+            .rev()
+            .find(|entry| {
+                entry.len() > prefix.len() && entry.starts_with(prefix) && !entry.contains('\n')
+            })
+            .map(String::as_str)
+    }
+
+    // The most recent `count` entries containing the pattern, along with
+    // their number as used by `!n`.
+    pub(crate) fn recent(&self, count: Option<usize>, pattern: &str) -> Vec<(usize, &str)> {
+        let matching: Vec<(usize, &str)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.contains(pattern))
+            .map(|(idx, entry)| (idx + 1, entry.as_str()))
+            .collect();
+        let skip = count.map_or(0, |count| matching.len().saturating_sub(count));
+        matching.into_iter().skip(skip).collect()
+    }
+
+    // Removes every entry, emptying the history file if any.
+    pub(crate) fn clear(&mut self) -> Result<()> {
+        self.entries.clear();
+        self.reset();
+        if let Some(path) = &self.file {
+            truncate(path).map_err(CapriceError::HistoryFile)?;
+        }
+        Ok(())
+    }
+
+    // Expands the `!!`, `!n`, `!-n`, `!prefix` and `!$` references to the
+    // history. References within single quotes or escaped by a backslash are
+    // left as is, as is a `!` followed by whitespace, `=` or `(`.
+    pub(crate) fn expand(&self, line: &str) -> Expansion {
+        let mut expanded = String::with_capacity(line.len());
+        let mut changed = false;
+        let mut quoted = false;
+        let mut rest = line;
+        while let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+            match c {
+                '\'' => quoted = !quoted,
+                '\\' if rest.starts_with('!') => {
+                    expanded.push_str("\\!");
+                    rest = &rest[1..];
+                    continue;
+                }
+                '!' if !quoted => {
+                    let len = reference_len(rest);
+                    if len > 0 {
+                        let reference = &rest[..len];
+                        match self.resolve(reference) {
+                            Some(entry) => expanded.push_str(&entry),
+                            None => return Expansion::NotFound(format!("!{}", reference)),
+                        }
+                        rest = &rest[len..];
+                        changed = true;
+                        continue;
+                    }
+                }
+                _ => (),
+            }
+            expanded.push(c);
+        }
+
+        if changed {
+            Expansion::Expanded(expanded)
+        } else {
+            Expansion::Unchanged
+        }
+    }
+
+    // The text referred to by what follows a `!`.
+    fn resolve(&self, reference: &str) -> Option<String> {
+        let last = self.entries.last();
+        match reference {
+            "!" => last.cloned(),
+            "$" => last
+                .and_then(|entry| entry.split_whitespace().last())
+                .map(str::to_owned),
+            _ => if let Some(n) = reference.strip_prefix('-') {
+                let n: usize = n.parse().ok()?;
+                self.entries
+                    .len()
+                    .checked_sub(n)
+                    .and_then(|idx| self.get(idx))
+            } else if let Ok(n) = reference.parse::<usize>() {
+                n.checked_sub(1).and_then(|idx| self.get(idx))
+            } else {
+                self.entries
+                    .iter()
+                    .rev()
+                    .find(|entry| entry.starts_with(reference))
+                    .map(String::as_str)
+            }
+            .map(str::to_owned),
+        }
+    }
+
+    // Index of the most recent entry older than `before` containing the query.
+    pub(crate) fn search(&self, query: &str, before: usize) -> Option<usize> {
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .rposition(|entry| entry.contains(query))
+    }
+
+    // Returns the entry older than the recalled one. The current line is kept
+    // as a draft when the navigation starts.
+    pub(crate) fn previous(&mut self, current: &str) -> Option<&str> {
+        let idx = match self.idx {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = current.to_owned();
+                self.entries.len() - 1
+            }
+            Some(0) => return None,
+            Some(idx) => idx - 1,
+        };
+        self.idx = Some(idx);
+        self.entries.get(idx).map(String::as_str)
+    }
+
+    // Returns the entry newer than the recalled one, or the draft when moving
+    // past the newest entry.
+    pub(crate) fn next(&mut self) -> Option<&str> {
+        let idx = self.idx? + 1;
+        if idx == self.entries.len() {
+            self.idx = None;
+            Some(&self.draft)
+        } else {
+            self.idx = Some(idx);
+            self.entries.get(idx).map(String::as_str)
+        }
+    }
+}
+
+// Length of the history reference following a `!`, zero if there is none.
+fn reference_len(s: &str) -> usize {
+    if s.starts_with(['=', '(']) {
+        return 0;
+    }
+    if s.starts_with('!') || s.starts_with('$') {
+        return 1;
+    }
+    if let Some(n) = s.strip_prefix('-') {
+        let digits = n.len() - n.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        return if digits > 0 { 1 + digits } else { 0 };
+    }
+    s.find(|c: char| c.is_whitespace() || c == '\'' || c == '!')
+        .unwrap_or(s.len())
+}
+
+fn read_entries(path: &Path) -> io::Result<Vec<String>> {
+    let mut file = match File::open(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        file => file?,
+    };
+    file.lock_shared()?;
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
+    Ok(lines(&content)
+        .filter_map(|line| std::str::from_utf8(line).ok())
+        .map(unescape)
+        .collect())
+}
+
+// Lines of the file, which may not all be valid UTF-8.
+fn lines(content: &[u8]) -> impl Iterator<Item = &[u8]> {
+    content
+        .strip_suffix(b"\n")
+        .unwrap_or(content)
+        .split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.is_empty())
+}
+
+// Appends the entry while holding an exclusive lock on the file, so that
+// concurrent sessions don't clobber each other, dropping the oldest entries
+// past `max_size`.
+fn append_entry(path: &Path, line: &str, max_size: usize) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)?;
+    file.lock()?;
+    writeln!(file, "{}", escape(line))?;
+
+    let mut content = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut content)?;
+    let count = lines(&content).count();
+    if count > max_size {
+        let mut kept = Vec::new();
+        for line in lines(&content).skip(count - max_size) {
+            kept.extend_from_slice(line);
+            kept.push(b'\n');
+        }
+        file.set_len(0)?;
+        file.write_all(&kept)?;
+    }
+    Ok(())
+}
+
+// Empties the file once the lock is held, rather than when opening it.
+fn truncate(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    file.lock()?;
+    file.set_len(0)
+}
+
+// Entries are stored one per line, multi-line entries have their newlines
+// escaped.
+fn escape(line: &str) -> String {
+    line.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut unescaped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                unescaped.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                unescaped.push('\\');
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn navigate_and_restore_draft() {
+        let mut history = History::new();
+        assert_eq!(history.previous("typed"), None);
+        assert_eq!(history.next(), None);
+
+        history.add("first").unwrap();
+        history.add("  ").unwrap();
+        history.add("second").unwrap();
+        assert_eq!(history.previous("typed"), Some("second"));
+        assert_eq!(history.previous("second"), Some("first"));
+        assert_eq!(history.previous("first"), None);
+        assert_eq!(history.next(), Some("second"));
+        assert_eq!(history.next(), Some("typed"));
+        assert_eq!(history.next(), None);
+
+        // submitting restarts from the newest entry
+        history.previous("");
+        history.add("third").unwrap();
+        assert_eq!(history.previous(""), Some("third"));
+        assert_eq!(history.next(), Some(""));
+    }
+
+    #[test]
+    fn suggestions() {
+        let mut history = History::new();
+        for line in ["set color red", "set(\nsize)", "set size 2", "set", "get"] {
+            history.add(line).unwrap();
+        }
+        assert_eq!(history.suggest(""), None);
+        assert_eq!(history.suggest("set"), Some("set size 2"));
+        assert_eq!(history.suggest("set c"), Some("set color red"));
+        assert_eq!(history.suggest("set("), None);
+        assert_eq!(history.suggest("get"), None);
+    }
+
+    #[test]
+    fn expansion() {
+        let mut history = History::new();
+        assert_eq!(history.expand("!!"), Expansion::NotFound("!!".to_owned()));
+        for line in ["set color red", "get color", "list"] {
+            history.add(line).unwrap();
+        }
+
+        let expand = |line| match history.expand(line) {
+            Expansion::Expanded(line) => line,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(expand("!!"), "list");
+        assert_eq!(expand("!1 !-2"), "set color red get color");
+        assert_eq!(expand("print !$"), "print list");
+        assert_eq!(expand("!se blue"), "set color red blue");
+        assert_eq!(expand("print !get!!"), "print get colorlist");
+
+        assert_eq!(history.expand("hi ! '!!' \\!!"), Expansion::Unchanged);
+        assert_eq!(history.expand("filter a!=3"), Expansion::Unchanged);
+        assert_eq!(history.expand("eval !(a)"), Expansion::Unchanged);
+        assert_eq!(history.expand("hi!\tthere"), Expansion::Unchanged);
+        assert_eq!(history.expand("!4"), Expansion::NotFound("!4".to_owned()));
+        assert_eq!(history.expand("!-0"), Expansion::NotFound("!-0".to_owned()));
+        assert_eq!(
+            history.expand("!xyz arg"),
+            Expansion::NotFound("!xyz".to_owned())
+        );
+    }
+
+    #[test]
+    fn recent_entries() {
+        let mut history = History::new();
+        for line in ["set red", "get", "set blue", "list"] {
+            history.add(line).unwrap();
+        }
+        assert_eq!(
+            history.recent(Some(2), ""),
+            vec![(3, "set blue"), (4, "list")]
+        );
+        assert_eq!(
+            history.recent(None, "set"),
+            vec![(1, "set red"), (3, "set blue")]
+        );
+        assert_eq!(history.recent(Some(1), "set"), vec![(3, "set blue")]);
+        assert!(history.recent(Some(0), "").is_empty());
+
+        history.clear().unwrap();
+        assert!(history.recent(None, "").is_empty());
+    }
+
+    #[test]
+    fn ignore_options() {
+        let mut history = History::new();
+        history.ignore_dups = true;
+        history.ignore_space = true;
+        history.max_size = 2;
+        for line in ["one", "one", " secret", "two", "three", "three"] {
+            history.add(line).unwrap();
+        }
+        assert_eq!(history.entries, vec!["two", "three"]);
+    }
+
+    #[test]
+    fn history_file() {
+        let path = std::env::temp_dir().join(format!("caprice_history_{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut history = History::new();
+        history.file = Some(path.clone());
+        history.max_size = 3;
+        history.load().unwrap();
+        assert!(history.entries.is_empty());
+        for line in ["one", "two \\ \\n", "(three\nfour)", "five"] {
+            history.add(line).unwrap();
+        }
+
+        let mut restarted = History::new();
+        restarted.file = Some(path.clone());
+        restarted.load().unwrap();
+        assert_eq!(
+            restarted.entries,
+            vec!["two \\ \\n", "(three\nfour)", "five"]
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+
+        // lines that aren't valid UTF-8 are skipped
+        std::fs::write(&path, b"one\n\xff\xfe\nthree\n").unwrap();
+        restarted.load().unwrap();
+        assert_eq!(restarted.entries, vec!["one", "three"]);
+        std::fs::remove_file(&path).unwrap();
+
+        // the entry is kept when the file can't be written
+        restarted.file = Some(path.join("not_a_dir"));
+        assert!(restarted.add("four").is_err());
+        assert_eq!(restarted.get(2), Some("four"));
+    }
+}
@@ -0,0 +1,205 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+
+/// Editor actions that can be bound to keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Action {
+    /// Submit the current line.
+    AcceptLine,
+    /// Show, or cycle through, the autocomplete suggestions.
+    Complete,
+    /// Exit the REPL, unless disabled with `CapriceBuilder::disable_ctrl_c`.
+    Interrupt,
+    /// Delete the character before the cursor.
+    BackwardDeleteChar,
+    /// Delete the character under the cursor.
+    DeleteChar,
+    /// Signal the end of input on an empty line, delete the character under
+    /// the cursor otherwise.
+    EndOfFile,
+    /// Move the cursor one character to the left.
+    BackwardChar,
+    /// Move the cursor one character to the right.
+    ForwardChar,
+    /// Move the cursor to the start of the line.
+    BeginningOfLine,
+    /// Move the cursor to the end of the line.
+    EndOfLine,
+    /// Replace the line with the previous entry of the history.
+    PreviousHistory,
+    /// Replace the line with the next entry of the history, or the line typed
+    /// before navigating the history.
+    NextHistory,
+    /// Search the history incrementally for lines containing the typed text.
+    /// While searching, steps to the next older match.
+    ReverseSearchHistory,
+    /// Cancel the history search, restoring the input.
+    Abort,
+    /// Kill from the cursor to the end of the line.
+    KillLine,
+    /// Kill from the start of the line to the cursor.
+    BackwardKillLine,
+    /// Kill the word before the cursor.
+    BackwardKillWord,
+    /// Insert the most recently killed text.
+    Yank,
+    /// Replace the text inserted by the previous yank with an older kill.
+    YankPop,
+    /// Revert the last edit of the current line.
+    Undo,
+    /// Reapply the last undone edit.
+    Redo,
+    /// Send the provided token to the host application, as if it was typed
+    /// and submitted. The current line is left untouched.
+    EmitToken(String),
+}
+
+/// Maps key events to editor [`Action`]s.
+///
+/// `Keymap::default()` holds the default emacs like bindings, which can be
+/// overridden or removed:
+///
+/// ```rust
+/// use caprice::keymap::{Action, Keymap};
+/// use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+///
+/// let keymap = Keymap::default()
+///     // complete with ctrl+space instead of tab
+///     .unbind(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE))
+///     .bind(KeyEvent::new(KeyCode::Char(' '), KeyModifiers::CONTROL), Action::Complete)
+///     // send `help` to the host application when pressing F1
+///     .bind(KeyEvent::new(KeyCode::F(1), KeyModifiers::NONE), Action::EmitToken("help".to_owned()));
+/// ```
+///
+/// Printable characters that are not bound to an action are inserted into
+/// the current line.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<(KeyCode, KeyModifiers), Action>,
+}
+
+impl Keymap {
+    /// Creates a keymap without any bindings.
+    pub fn empty() -> Self {
+        Keymap {
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Binds the key to the action, replacing any previous binding.
+    pub fn bind(mut self, key: KeyEvent, action: Action) -> Self {
+        self.bindings.insert(normalize(key), action);
+        self
+    }
+
+    /// Removes the binding of the key, if any.
+    pub fn unbind(mut self, key: KeyEvent) -> Self {
+        self.bindings.remove(&normalize(key));
+        self
+    }
+
+    /// Returns the action bound to the key.
+    pub fn get(&self, key: KeyEvent) -> Option<&Action> {
+        self.bindings.get(&normalize(key))
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+
+        Keymap::empty()
+            .bind(key(KeyCode::Enter), Action::AcceptLine)
+            .bind(key(KeyCode::Tab), Action::Complete)
+            .bind(ctrl('c'), Action::Interrupt)
+            .bind(ctrl('d'), Action::EndOfFile)
+            .bind(key(KeyCode::Backspace), Action::BackwardDeleteChar)
+            .bind(key(KeyCode::Delete), Action::DeleteChar)
+            .bind(key(KeyCode::Left), Action::BackwardChar)
+            .bind(key(KeyCode::Right), Action::ForwardChar)
+            .bind(key(KeyCode::Home), Action::BeginningOfLine)
+            .bind(key(KeyCode::End), Action::EndOfLine)
+            .bind(key(KeyCode::Up), Action::PreviousHistory)
+            .bind(key(KeyCode::Down), Action::NextHistory)
+            .bind(ctrl('a'), Action::BeginningOfLine)
+            .bind(ctrl('e'), Action::EndOfLine)
+            .bind(ctrl('p'), Action::PreviousHistory)
+            .bind(ctrl('n'), Action::NextHistory)
+            .bind(ctrl('r'), Action::ReverseSearchHistory)
+            .bind(ctrl('g'), Action::Abort)
+            .bind(key(KeyCode::Esc), Action::Abort)
+            .bind(ctrl('k'), Action::KillLine)
+            .bind(ctrl('u'), Action::BackwardKillLine)
+            .bind(ctrl('w'), Action::BackwardKillWord)
+            .bind(ctrl('y'), Action::Yank)
+            .bind(
+                KeyEvent::new(KeyCode::Char('y'), KeyModifiers::ALT),
+                Action::YankPop,
+            )
+            .bind(ctrl('_'), Action::Undo)
+            // ctrl+_ is reported as ctrl+7 by most unix terminals
+            .bind(ctrl('7'), Action::Undo)
+            .bind(ctrl('z'), Action::Undo)
+            .bind(
+                KeyEvent::new(KeyCode::Char('_'), KeyModifiers::ALT),
+                Action::Redo,
+            )
+    }
+}
+
+// Terminals report shift for upper case characters inconsistently, the
+// character itself already tells whether shift was pressed.
+fn normalize(key: KeyEvent) -> (KeyCode, KeyModifiers) {
+    match key.code {
+        KeyCode::Char(_) => (key.code, key.modifiers - KeyModifiers::SHIFT),
+        _ => (key.code, key.modifiers),
+    }
+}
+
+// Whether a character typed with the modifiers is text to insert. Windows
+// reports AltGr as control and alt, only one of them makes a shortcut.
+pub(crate) fn is_text(modifiers: KeyModifiers) -> bool {
+    let altgr = KeyModifiers::CONTROL | KeyModifiers::ALT;
+    !modifiers.intersects(altgr) || modifiers.contains(altgr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn override_and_unbind() {
+        let tab = KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE);
+        let f1 = KeyEvent::new(KeyCode::F(1), KeyModifiers::NONE);
+
+        let keymap = Keymap::default();
+        assert_eq!(keymap.get(tab), Some(&Action::Complete));
+        assert_eq!(keymap.get(f1), None);
+
+        let keymap = keymap
+            .bind(tab, Action::EmitToken("help".to_owned()))
+            .unbind(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(keymap.get(tab), Some(&Action::EmitToken("help".to_owned())));
+        assert_eq!(
+            keymap.get(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)),
+            None
+        );
+    }
+
+    #[test]
+    fn shift_is_ignored_for_chars() {
+        let keymap = Keymap::empty().bind(
+            KeyEvent::new(KeyCode::Char('Y'), KeyModifiers::ALT),
+            Action::YankPop,
+        );
+        assert_eq!(
+            keymap.get(KeyEvent::new(
+                KeyCode::Char('Y'),
+                KeyModifiers::ALT | KeyModifiers::SHIFT
+            )),
+            Some(&Action::YankPop)
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+
+// Maximum number of killed strings remembered by the kill ring.
+const KILL_RING_SIZE: usize = 16;
+
+// Where killed text goes relative to the most recent kill, when successive
+// kills are merged into a single entry.
+pub(crate) enum KillDirection {
+    Forward,
+    Backward,
+}
+
+#[derive(Debug)]
+pub(crate) struct KillRing {
+    entries: VecDeque<String>,
+    idx: usize,
+}
+
+impl KillRing {
+    pub(crate) fn new() -> Self {
+        KillRing {
+            entries: VecDeque::with_capacity(KILL_RING_SIZE),
+            idx: 0,
+        }
+    }
+
+    // Stores killed text as the newest entry of the ring, dropping the oldest
+    // one if the ring is full.
+    pub(crate) fn kill(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if self.entries.len() == KILL_RING_SIZE {
+            self.entries.pop_back();
+        }
+        self.entries.push_front(text.to_owned());
+        self.idx = 0;
+    }
+
+    // Merges killed text with the newest entry, so that consecutive kills
+    // can be yanked back at once.
+    pub(crate) fn kill_merge(&mut self, text: &str, direction: KillDirection) {
+        match self.entries.front_mut() {
+            Some(front) => {
+                match direction {
+                    KillDirection::Forward => front.push_str(text),
+                    KillDirection::Backward => front.insert_str(0, text),
+                }
+                self.idx = 0;
+            }
+            None => self.kill(text),
+        }
+    }
+
+    // Returns the newest entry.
+    pub(crate) fn yank(&mut self) -> Option<&str> {
+        self.idx = 0;
+        self.entries.front().map(String::as_str)
+    }
+
+    // Returns the entry preceding the last yanked one, wrapping around to the
+    // newest entry.
+    pub(crate) fn rotate(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.idx = (self.idx + 1) % self.entries.len();
+        self.entries.get(self.idx).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yank_and_rotate() {
+        let mut ring = KillRing::new();
+        assert_eq!(ring.yank(), None);
+        assert_eq!(ring.rotate(), None);
+
+        ring.kill("one");
+        ring.kill("");
+        ring.kill("two");
+        ring.kill("three");
+        assert_eq!(ring.yank(), Some("three"));
+        assert_eq!(ring.rotate(), Some("two"));
+        assert_eq!(ring.rotate(), Some("one"));
+        assert_eq!(ring.rotate(), Some("three"));
+        assert_eq!(ring.yank(), Some("three"));
+    }
+
+    #[test]
+    fn merged_kills() {
+        let mut ring = KillRing::new();
+        ring.kill_merge("some", KillDirection::Backward);
+        ring.kill_merge("_token", KillDirection::Forward);
+        ring.kill_merge("other ", KillDirection::Backward);
+        assert_eq!(ring.yank(), Some("other some_token"));
+        assert_eq!(ring.rotate(), Some("other some_token"));
+    }
+
+    #[test]
+    fn oldest_entry_is_dropped() {
+        let mut ring = KillRing::new();
+        for i in 0..=KILL_RING_SIZE {
+            ring.kill(&i.to_string());
+        }
+        assert_eq!(ring.yank(), Some(KILL_RING_SIZE.to_string().as_str()));
+        for _ in 0..KILL_RING_SIZE - 1 {
+            ring.rotate();
+        }
+        assert_eq!(ring.rotate(), Some(KILL_RING_SIZE.to_string().as_str()));
+    }
+}
@@ -5,7 +5,7 @@
 //!
 //! # Example running `Caprice` on a separate thread:
 //! ```rust, no_run
-//! use caprice::{Caprice, CapriceCommand};
+//! use caprice::{Caprice, CapriceCommand, CapriceInput};
 //! use std::thread;
 //! use std::time::Duration;
 //!
@@ -32,7 +32,12 @@
 //!
 //! loop {
 //!     // check if we received a token from caprice
-//!     if let Ok(token) = rx.try_recv() {
+//!     if let Ok(input) = rx.try_recv() {
+//!         let token = match input {
+//!             CapriceInput::Keyword(token) => token,
+//!             // treat ctrl+d on an empty line as exit
+//!             _ => "exit".to_owned(),
+//!         };
 //!         match token.as_str() {
 //!             // leave if the user types exit
 //!             "exit" => {
@@ -57,10 +62,24 @@
 
 mod autocomplete;
 pub mod caprice;
+pub mod completion;
 mod engine;
 pub mod error;
+mod history;
+pub mod keymap;
+mod kill_ring;
 mod scanner;
+mod search;
+mod secret;
 mod terminal;
 pub mod theme;
+mod undo;
+mod unicode;
+mod vi;
 pub use self::caprice::Caprice;
 pub use self::caprice::CapriceCommand;
+pub use self::caprice::CapriceInput;
+pub use self::caprice::CharPolicy;
+pub use self::caprice::MatchingMode;
+pub use self::caprice::PastePolicy;
+pub use self::caprice::SecretEcho;
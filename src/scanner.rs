@@ -1,17 +1,59 @@
+use crate::caprice::{CharPolicy, PastePolicy};
+use crate::keymap::{self, Action, Keymap};
+use crate::kill_ring::{KillDirection, KillRing};
+use crate::undo::{EditHistory, Snapshot};
+use crate::unicode;
+use crate::vi::{self, InsertAt, Motion, Operator, ViCommand, ViMode, ViState};
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use std::collections::VecDeque;
 
 pub(crate) enum TokenType {
     Token(String),
-    BackSpace,
-    Continue(String),
-    Tab(String),
+    Continue,
+    // The line was accepted but the input continues on the next line.
+    Newline,
+    Cursor,
+    Tab,
+    Emit(String),
+    Eof,
+    PreviousHistory,
+    NextHistory,
+    SearchHistory,
+    Resize,
     Exit,
     None,
 }
 
+// The previous editing operation, used to merge successive kills and to
+// rotate the text inserted by the last yank.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LastEdit {
+    Kill,
+    // Byte offset where the last yanked text begins. The text ends at the cursor.
+    Yank(usize),
+    // Consecutive inserted chars are undone at once.
+    Insert,
+    Undo,
+    Other,
+}
+
 #[derive(Debug)]
 pub(crate) struct Scanner {
     buffer: String,
+    // Byte offset of the cursor into `buffer`, always on a grapheme cluster boundary.
+    cursor: usize,
+    // Previous lines of a multi-line input, joined.
+    pending: String,
+    kill_ring: KillRing,
+    edit_history: EditHistory,
+    last_edit: LastEdit,
+    // Vi editing state, `None` when using the default emacs bindings.
+    vi: Option<ViState>,
+    pub(crate) keymap: Keymap,
+    pub(crate) paste_policy: PastePolicy,
+    pub(crate) char_policy: CharPolicy,
+    // Pasted lines waiting to be submitted.
+    pasted_lines: VecDeque<String>,
     pub(crate) enable_ctrl_c: bool,
 }
 
@@ -19,41 +61,264 @@ impl Scanner {
     pub(crate) fn new() -> Self {
         Scanner {
             buffer: String::new(),
+            cursor: 0,
+            pending: String::new(),
+            kill_ring: KillRing::new(),
+            edit_history: EditHistory::new(),
+            last_edit: LastEdit::Other,
+            vi: None,
+            keymap: Keymap::default(),
+            paste_policy: PastePolicy::SubmitEachLine,
+            char_policy: CharPolicy::Restricted,
+            pasted_lines: VecDeque::new(),
             enable_ctrl_c: true,
         }
     }
 
     pub(crate) fn scan(&mut self, input_event: Event) -> TokenType {
+        // A resize is not an edit, the previous one can still be merged with
+        // the next or rotated by a yank pop.
+        if let Event::Resize(..) = input_event {
+            return TokenType::Resize;
+        }
+
+        let last_edit = std::mem::replace(&mut self.last_edit, LastEdit::Other);
+        let before = (self.buffer.clone(), self.cursor);
+
+        let token_type = self.scan_event(input_event, last_edit);
+
+        // Record the edit, merging consecutive inserted chars into one step
+        let merge = self.last_edit == LastEdit::Insert && last_edit == LastEdit::Insert;
+        if self.buffer != before.0 && self.last_edit != LastEdit::Undo && !merge {
+            self.edit_history.record(before);
+        }
+        token_type
+    }
+
+    fn scan_event(&mut self, input_event: Event, last_edit: LastEdit) -> TokenType {
+        if let (Some(mode), Event::Key(key)) = (self.vi_mode(), &input_event) {
+            let key = *key;
+            match (mode, key.code) {
+                (ViMode::Insert, KeyCode::Esc) => return self.scan_vi_escape(),
+                (ViMode::Normal, KeyCode::Enter) => (),
+                (ViMode::Normal, _) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return self.scan_vi_normal(key)
+                }
+                _ => (),
+            }
+        }
+
         match input_event {
-            Event::Key(KeyEvent {
-                code: KeyCode::Tab, ..
-            }) => self.scan_tab(),
-            Event::Key(KeyEvent {
-                code: KeyCode::Enter,
-                ..
-            }) => self.scan_enter(),
-            Event::Key(KeyEvent {
-                code: KeyCode::Char('c'),
-                modifiers: KeyModifiers::CONTROL,
-            }) => self.scan_ctrl_c(),
-            Event::Key(KeyEvent {
-                code: KeyCode::Char(c),
-                ..
-            }) => self.scan_char(c),
-            Event::Key(KeyEvent {
-                code: KeyCode::Backspace,
-                ..
-            }) => self.scan_backspace(),
+            Event::Key(key) => match self.keymap.get(key).cloned() {
+                Some(action) => self.scan_action(action, last_edit),
+                None => self.scan_unbound(key),
+            },
+            Event::Paste(text) => self.scan_paste(&text),
+            _ => TokenType::None,
+        }
+    }
+
+    // Inserts pasted text at the cursor in one step. Newlines are handled
+    // according to the paste policy.
+    fn scan_paste(&mut self, text: &str) -> TokenType {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        match self.paste_policy {
+            PastePolicy::Reject if text.contains('\n') => TokenType::None,
+            PastePolicy::Reject => self.insert_str(&text),
+            PastePolicy::Join => self.insert_str(&text.replace('\n', " ")),
+            PastePolicy::SubmitEachLine => {
+                let mut lines = text.split('\n').map(str::to_owned);
+                let first = lines.next().unwrap_or_default();
+                self.pasted_lines.extend(lines);
+                if self.pasted_lines.is_empty() {
+                    self.insert_str(&first)
+                } else {
+                    self.insert_str(&first);
+                    self.scan_enter()
+                }
+            }
+        }
+    }
+
+    // Continues a paste whose lines are submitted one at a time: inserts the
+    // next pasted line and submits it, unless it is the last one. Returns
+    // `None` if there is no paste in progress.
+    pub(crate) fn scan_pasted_line(&mut self) -> Option<TokenType> {
+        let line = self.pasted_lines.pop_front()?;
+        self.insert_str(&line);
+        if self.pasted_lines.is_empty() {
+            Some(TokenType::Continue)
+        } else {
+            Some(self.scan_enter())
+        }
+    }
+
+    // Inserts the accepted chars of the string at the cursor.
+    fn insert_str(&mut self, s: &str) -> TokenType {
+        let accepted: String = s.chars().filter(|&c| self.char_policy.accepts(c)).collect();
+        if accepted.is_empty() {
+            return TokenType::None;
+        }
+        self.buffer.insert_str(self.cursor, &accepted);
+        self.cursor += accepted.len();
+        TokenType::Continue
+    }
+
+    fn scan_action(&mut self, action: Action, last_edit: LastEdit) -> TokenType {
+        match action {
+            Action::AcceptLine => self.scan_enter(),
+            Action::Complete => self.scan_tab(),
+            Action::Interrupt => self.scan_ctrl_c(),
+            Action::BackwardDeleteChar => self.scan_backspace(),
+            Action::DeleteChar => self.scan_delete(),
+            Action::EndOfFile => self.scan_ctrl_d(),
+            Action::BackwardChar => self.scan_left(),
+            Action::ForwardChar => self.scan_right(),
+            Action::BeginningOfLine => self.scan_home(),
+            Action::EndOfLine => self.scan_end(),
+            Action::PreviousHistory => TokenType::PreviousHistory,
+            Action::NextHistory => TokenType::NextHistory,
+            Action::ReverseSearchHistory => TokenType::SearchHistory,
+            Action::Abort => TokenType::None,
+            Action::KillLine => self.scan_kill_line(last_edit),
+            Action::BackwardKillLine => self.scan_kill_line_backward(last_edit),
+            Action::BackwardKillWord => self.scan_kill_word_backward(last_edit),
+            Action::Yank => self.scan_yank(),
+            Action::YankPop => self.scan_yank_rotate(last_edit),
+            Action::Undo => self.scan_undo(),
+            Action::Redo => self.scan_redo(),
+            Action::EmitToken(token) => TokenType::Emit(token),
+        }
+    }
+
+    // Keys without a binding insert their character, unless combined with
+    // either control or alt.
+    fn scan_unbound(&mut self, key: KeyEvent) -> TokenType {
+        match key.code {
+            KeyCode::Char(c) if keymap::is_text(key.modifiers) => self.scan_char(c),
             _ => TokenType::None,
         }
     }
 
+    // The action bound to the key event, if any.
+    pub(crate) fn action(&self, event: &Event) -> Option<&Action> {
+        match event {
+            Event::Key(key) => self.keymap.get(*key),
+            _ => None,
+        }
+    }
+
     pub(crate) fn scan_backspace(&mut self) -> TokenType {
-        if !self.buffer.is_empty() {
-            self.buffer.pop();
-            TokenType::BackSpace
+        match self.prev_boundary() {
+            Some(prev) => {
+                self.buffer.replace_range(prev..self.cursor, "");
+                self.cursor = prev;
+                TokenType::Continue
+            }
+            None => TokenType::None,
+        }
+    }
+
+    pub(crate) fn scan_delete(&mut self) -> TokenType {
+        match self.next_boundary() {
+            Some(next) => {
+                self.buffer.replace_range(self.cursor..next, "");
+                TokenType::Continue
+            }
+            None => TokenType::None,
+        }
+    }
+
+    pub(crate) fn scan_left(&mut self) -> TokenType {
+        match self.prev_boundary() {
+            Some(prev) => {
+                self.cursor = prev;
+                TokenType::Cursor
+            }
+            None => TokenType::None,
+        }
+    }
+
+    pub(crate) fn scan_right(&mut self) -> TokenType {
+        match self.next_boundary() {
+            Some(next) => {
+                self.cursor = next;
+                TokenType::Cursor
+            }
+            None => TokenType::None,
+        }
+    }
+
+    pub(crate) fn scan_home(&mut self) -> TokenType {
+        self.move_cursor(0)
+    }
+
+    pub(crate) fn scan_end(&mut self) -> TokenType {
+        self.move_cursor(self.buffer.len())
+    }
+
+    // Kills from the cursor to the end of the line.
+    fn scan_kill_line(&mut self, last_edit: LastEdit) -> TokenType {
+        self.kill(self.cursor, self.buffer.len(), last_edit)
+    }
+
+    // Kills from the start of the line to the cursor.
+    fn scan_kill_line_backward(&mut self, last_edit: LastEdit) -> TokenType {
+        self.kill(0, self.cursor, last_edit)
+    }
+
+    // Kills the whitespace delimited word before the cursor.
+    fn scan_kill_word_backward(&mut self, last_edit: LastEdit) -> TokenType {
+        let before = self.buffer[..self.cursor].trim_end();
+        let start = before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map_or(0, |(idx, c)| idx + c.len_utf8());
+        self.kill(start, self.cursor, last_edit)
+    }
+
+    // Inserts the most recently killed text at the cursor.
+    pub(crate) fn scan_yank(&mut self) -> TokenType {
+        match self.kill_ring.yank() {
+            Some(text) => {
+                let text = text.to_owned();
+                let start = self.cursor;
+                self.buffer.insert_str(start, &text);
+                self.cursor += text.len();
+                self.last_edit = LastEdit::Yank(start);
+                TokenType::Continue
+            }
+            None => TokenType::None,
+        }
+    }
+
+    // Replaces the text inserted by the previous yank with the preceding
+    // entry of the kill ring. Only valid right after a yank.
+    fn scan_yank_rotate(&mut self, last_edit: LastEdit) -> TokenType {
+        let start = match last_edit {
+            LastEdit::Yank(start) => start,
+            _ => return TokenType::None,
+        };
+        match self.kill_ring.rotate() {
+            Some(text) => {
+                let text = text.to_owned();
+                self.buffer.replace_range(start..self.cursor, &text);
+                self.cursor = start + text.len();
+                self.last_edit = LastEdit::Yank(start);
+                TokenType::Continue
+            }
+            None => TokenType::None,
+        }
+    }
+
+    // Signals the end of input on an empty line, deletes the char under the
+    // cursor otherwise.
+    pub(crate) fn scan_ctrl_d(&mut self) -> TokenType {
+        if self.buffer.is_empty() && !self.is_continuation() {
+            TokenType::Eof
         } else {
-            TokenType::None
+            self.scan_delete()
         }
     }
 
@@ -66,28 +331,628 @@ impl Scanner {
     }
 
     pub(crate) fn scan_tab(&mut self) -> TokenType {
-        TokenType::Tab(self.buffer.clone())
+        TokenType::Tab
     }
 
+    // Submits the input, unless the line ends in a backslash or leaves quotes
+    // or brackets open, in which case the input continues on a new line.
     pub(crate) fn scan_enter(&mut self) -> TokenType {
-        let token = self.buffer.clone();
-        TokenType::Token(token)
+        let input = format!("{}{}", self.pending, self.buffer);
+        if ends_in_line_continuation(&input) || is_unbalanced(&input) {
+            TokenType::Newline
+        } else {
+            TokenType::Token(input)
+        }
+    }
+
+    // Moves the current line to the pending lines of a multi-line input. A
+    // trailing backslash joins the line with the next one, otherwise they
+    // are separated by a newline.
+    pub(crate) fn continue_input(&mut self) {
+        let mut input = format!("{}{}", self.pending, self.buffer);
+        if ends_in_line_continuation(&input) {
+            input.pop();
+        } else {
+            input.push('\n');
+        }
+        self.pending = input;
+        self.buffer.clear();
+        self.cursor = 0;
+        self.edit_history.clear();
+    }
+
+    // Reverts the last edit of the line.
+    pub(crate) fn scan_undo(&mut self) -> TokenType {
+        let current = (self.buffer.clone(), self.cursor);
+        match self.edit_history.undo(current) {
+            Some(previous) => self.restore(previous),
+            None => TokenType::None,
+        }
+    }
+
+    // Reapplies the last undone edit.
+    pub(crate) fn scan_redo(&mut self) -> TokenType {
+        let current = (self.buffer.clone(), self.cursor);
+        match self.edit_history.redo(current) {
+            Some(next) => self.restore(next),
+            None => TokenType::None,
+        }
+    }
+
+    fn restore(&mut self, (buffer, cursor): Snapshot) -> TokenType {
+        self.buffer = buffer;
+        self.cursor = cursor;
+        self.last_edit = LastEdit::Undo;
+        TokenType::Continue
     }
 
     pub(crate) fn scan_char(&mut self, c: char) -> TokenType {
-        if c.is_alphanumeric() || c == '/' || c == '_' || c == ' ' {
-            self.buffer.push(c);
-            TokenType::Continue(self.buffer.clone())
+        if self.char_policy.accepts(c) {
+            self.buffer.insert(self.cursor, c);
+            self.cursor += c.len_utf8();
+            self.last_edit = LastEdit::Insert;
+            TokenType::Continue
         } else {
             TokenType::None
         }
     }
 
+    pub(crate) fn enable_vi_mode(&mut self) {
+        self.vi = Some(ViState::new());
+    }
+
+    // The current vi mode, `None` if vi mode is disabled.
+    pub(crate) fn vi_mode(&self) -> Option<ViMode> {
+        self.vi.as_ref().map(|vi| vi.mode)
+    }
+
+    // Leaves insert mode, moving the cursor onto the last inserted char.
+    fn scan_vi_escape(&mut self) -> TokenType {
+        if let Some(vi) = self.vi.as_mut() {
+            vi.mode = ViMode::Normal;
+        }
+        self.cursor = vi::motion_target(&self.buffer, self.cursor, Motion::Left);
+        TokenType::Cursor
+    }
+
+    fn scan_vi_normal(&mut self, key: KeyEvent) -> TokenType {
+        let command = match self.vi.as_mut() {
+            Some(vi) => vi.normal_key(key),
+            None => return TokenType::None,
+        };
+        match command {
+            ViCommand::Move(motion) => {
+                let target = vi::motion_target(&self.buffer, self.cursor, motion);
+                self.cursor = self.clamp_normal(target);
+                TokenType::Cursor
+            }
+            ViCommand::Operate(operator, motion) => self.scan_vi_operator(operator, motion),
+            ViCommand::Insert(at) => {
+                self.cursor = match at {
+                    InsertAt::Cursor => self.cursor,
+                    InsertAt::AfterCursor => {
+                        vi::motion_target(&self.buffer, self.cursor, Motion::Right)
+                    }
+                    InsertAt::LineStart => 0,
+                    InsertAt::LineEnd => self.buffer.len(),
+                };
+                self.enter_insert_mode();
+                TokenType::Cursor
+            }
+            ViCommand::Put { before } => {
+                let text = match self.kill_ring.yank() {
+                    Some(text) => text.to_owned(),
+                    None => return TokenType::None,
+                };
+                if !before {
+                    self.cursor = vi::motion_target(&self.buffer, self.cursor, Motion::Right);
+                }
+                self.buffer.insert_str(self.cursor, &text);
+                // rest on the last char of the put text
+                self.cursor =
+                    vi::motion_target(&self.buffer, self.cursor + text.len(), Motion::Left);
+                TokenType::Continue
+            }
+            ViCommand::Undo => self.scan_undo(),
+            ViCommand::History { previous: true } => TokenType::PreviousHistory,
+            ViCommand::History { previous: false } => TokenType::NextHistory,
+            ViCommand::Pending | ViCommand::None => TokenType::None,
+        }
+    }
+
+    // Applies a vi operator on the text between the cursor and the motion target.
+    fn scan_vi_operator(&mut self, operator: Operator, motion: Motion) -> TokenType {
+        let target = vi::motion_target(&self.buffer, self.cursor, motion);
+        let (start, end) = match motion {
+            Motion::Line => (0, self.buffer.len()),
+            _ => (self.cursor.min(target), self.cursor.max(target)),
+        };
+        if start == end && operator != Operator::Change {
+            return TokenType::None;
+        }
+
+        match operator {
+            Operator::Yank => {
+                self.kill_ring.kill(&self.buffer[start..end]);
+                self.cursor = self.clamp_normal(start);
+                TokenType::Cursor
+            }
+            Operator::Delete => {
+                let deleted: String = self.buffer.drain(start..end).collect();
+                self.kill_ring.kill(&deleted);
+                self.cursor = self.clamp_normal(start);
+                TokenType::Continue
+            }
+            Operator::Change => {
+                let deleted: String = self.buffer.drain(start..end).collect();
+                self.kill_ring.kill(&deleted);
+                self.cursor = start;
+                self.enter_insert_mode();
+                TokenType::Continue
+            }
+        }
+    }
+
+    fn enter_insert_mode(&mut self) {
+        if let Some(vi) = self.vi.as_mut() {
+            vi.mode = ViMode::Insert;
+        }
+    }
+
+    // In normal mode the cursor rests on a char, never past the end of the buffer.
+    fn clamp_normal(&self, position: usize) -> usize {
+        if position >= self.buffer.len() {
+            vi::motion_target(&self.buffer, self.buffer.len(), Motion::Left)
+        } else {
+            position
+        }
+    }
+
+    pub(crate) fn buffer(&self) -> &str {
+        &self.buffer
+    }
+
+    pub(crate) fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    // Whether the current line continues a multi-line input.
+    pub(crate) fn is_continuation(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    // Replaces the buffer as a single undoable edit, placing the cursor at its end.
     pub(crate) fn update_buffer(&mut self, new_buffer: String) {
+        let cursor = new_buffer.len();
+        self.complete(new_buffer, cursor);
+    }
+
+    // Replaces the buffer with a completed line as a single undoable edit.
+    pub(crate) fn complete(&mut self, new_buffer: String, cursor: usize) {
+        if new_buffer != self.buffer {
+            self.edit_history
+                .record((std::mem::take(&mut self.buffer), self.cursor));
+        }
         self.buffer = new_buffer;
+        self.cursor = cursor;
     }
 
+    // Replaces the buffer with a history entry as a single undoable edit.
+    // Entries spanning several lines are recalled on a single one.
+    pub(crate) fn recall(&mut self, line: &str) {
+        self.update_buffer(line.replace('\n', " "));
+        if self.vi_mode() == Some(ViMode::Normal) {
+            self.cursor = self.clamp_normal(self.cursor);
+        }
+    }
+
+    // Clears the buffer for a new line, which always starts in vi insert mode.
     pub(crate) fn clear_buffer(&mut self) {
         self.buffer.clear();
+        self.pending.clear();
+        self.cursor = 0;
+        self.edit_history.clear();
+        if let Some(vi) = self.vi.as_mut() {
+            vi.reset();
+        }
+    }
+
+    // Removes the `start..end` byte range from the buffer and stores it in
+    // the kill ring, merging it with the previous kill if there was one.
+    fn kill(&mut self, start: usize, end: usize, last_edit: LastEdit) -> TokenType {
+        if start == end {
+            self.last_edit = last_edit;
+            return TokenType::None;
+        }
+        let killed: String = self.buffer.drain(start..end).collect();
+        if last_edit == LastEdit::Kill {
+            let direction = if start < self.cursor {
+                KillDirection::Backward
+            } else {
+                KillDirection::Forward
+            };
+            self.kill_ring.kill_merge(&killed, direction);
+        } else {
+            self.kill_ring.kill(&killed);
+        }
+        self.cursor = start;
+        self.last_edit = LastEdit::Kill;
+        TokenType::Continue
+    }
+
+    fn move_cursor(&mut self, position: usize) -> TokenType {
+        if self.cursor == position {
+            TokenType::None
+        } else {
+            self.cursor = position;
+            TokenType::Cursor
+        }
+    }
+
+    fn prev_boundary(&self) -> Option<usize> {
+        unicode::prev_grapheme(&self.buffer, self.cursor)
+    }
+
+    fn next_boundary(&self) -> Option<usize> {
+        unicode::next_grapheme(&self.buffer, self.cursor)
+    }
+}
+
+// An odd number of trailing backslashes escapes the newline.
+fn ends_in_line_continuation(input: &str) -> bool {
+    input.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
+}
+
+// Whether the input leaves a quote or a bracket open.
+fn is_unbalanced(input: &str) -> bool {
+    let mut quote = None;
+    let mut depth = 0;
+    let mut escaped = false;
+
+    for c in input.chars() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            // backslashes are literal within single quotes
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => (),
+            (_, '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth -= 1,
+            _ => (),
+        }
+    }
+    quote.is_some() || depth > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scanner_with(buffer: &str, cursor: usize) -> Scanner {
+        let mut scanner = Scanner::new();
+        scanner.update_buffer(buffer.to_owned());
+        scanner.cursor = cursor;
+        scanner
+    }
+
+    #[test]
+    fn insert_at_cursor() {
+        let mut scanner = scanner_with("sme_token", 1);
+        scanner.scan_char('o');
+        assert_eq!(scanner.buffer(), "some_token");
+        assert_eq!(scanner.cursor(), 2);
+
+        scanner.scan_home();
+        scanner.scan_char('_');
+        assert_eq!(scanner.buffer(), "_some_token");
+        assert_eq!(scanner.cursor(), 1);
+    }
+
+    #[test]
+    fn remove_at_cursor() {
+        let mut scanner = scanner_with("somee_token", 5);
+        scanner.scan_backspace();
+        assert_eq!(scanner.buffer(), "some_token");
+        assert_eq!(scanner.cursor(), 4);
+
+        scanner.scan_delete();
+        assert_eq!(scanner.buffer(), "sometoken");
+        assert_eq!(scanner.cursor(), 4);
+
+        // nothing to delete past the end, nothing to erase before the start
+        scanner.scan_end();
+        assert!(matches!(scanner.scan_delete(), TokenType::None));
+        scanner.scan_home();
+        assert!(matches!(scanner.scan_backspace(), TokenType::None));
+        assert_eq!(scanner.buffer(), "sometoken");
+    }
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Event {
+        Event::Key(KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    fn kill_and_yank() {
+        let ctrl = |c| key(KeyCode::Char(c), KeyModifiers::CONTROL);
+        let mut scanner = scanner_with("set color red", 9);
+
+        scanner.scan(ctrl('k'));
+        assert_eq!(scanner.buffer(), "set color");
+        scanner.scan(ctrl('w'));
+        assert_eq!(scanner.buffer(), "set ");
+        scanner.scan(ctrl('a'));
+        scanner.scan(ctrl('y'));
+        assert_eq!(scanner.buffer(), "color redset ");
+        assert_eq!(scanner.cursor(), 9);
+
+        // rotating replaces the yanked text
+        scanner.scan(ctrl('e'));
+        scanner.scan(ctrl('u'));
+        assert_eq!(scanner.buffer(), "");
+        scanner.scan(ctrl('y'));
+        assert_eq!(scanner.buffer(), "color redset ");
+        scanner.scan(key(KeyCode::Char('y'), KeyModifiers::ALT));
+        assert_eq!(scanner.buffer(), "color red");
+        assert_eq!(scanner.cursor(), 9);
+
+        // but only right after a yank
+        scanner.scan(key(KeyCode::Left, KeyModifiers::NONE));
+        assert!(matches!(
+            scanner.scan(key(KeyCode::Char('y'), KeyModifiers::ALT)),
+            TokenType::None
+        ));
+
+        // words may be separated by multi-byte whitespace
+        let mut scanner = scanner_with("a\u{a0}b", 4);
+        scanner.scan(ctrl('w'));
+        assert_eq!(scanner.buffer(), "a\u{a0}");
+    }
+
+    #[test]
+    fn unbalanced_input() {
+        assert!(!is_unbalanced("set color red"));
+        assert!(is_unbalanced("say \"hello"));
+        assert!(!is_unbalanced("say \"hello (\""));
+        assert!(is_unbalanced("load { a: [1, 2]"));
+        assert!(!is_unbalanced("load { a: [1, 2] }"));
+        assert!(!is_unbalanced("close )"));
+        assert!(!is_unbalanced("say 'it\\'"));
+        assert!(!is_unbalanced("say \"\\\"\""));
+
+        assert!(ends_in_line_continuation("set \\"));
+        assert!(!ends_in_line_continuation("set \\\\"));
+    }
+
+    #[test]
+    fn multi_line_input() {
+        let enter = || key(KeyCode::Enter, KeyModifiers::NONE);
+        let mut scanner = scanner_with("set color \\", 11);
+        assert!(matches!(scanner.scan(enter()), TokenType::Newline));
+        scanner.continue_input();
+        assert!(scanner.is_continuation());
+        assert_eq!(scanner.buffer(), "");
+
+        scanner.update_buffer("{ red".to_owned());
+        assert!(matches!(scanner.scan(enter()), TokenType::Newline));
+        scanner.continue_input();
+        scanner.update_buffer("}".to_owned());
+        assert!(
+            matches!(scanner.scan(enter()), TokenType::Token(token) if token == "set color { red\n}")
+        );
+        scanner.clear_buffer();
+        assert!(!scanner.is_continuation());
+
+        // the continuing characters can be typed with the default policy
+        for c in "say (\"hi\") \\".chars() {
+            scanner.scan(key(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        assert_eq!(scanner.buffer(), "say (\"hi\") \\");
+        assert!(matches!(scanner.scan(enter()), TokenType::Newline));
+    }
+
+    #[test]
+    fn char_policies() {
+        let mut scanner = scanner_with("", 0);
+        for c in "set a=1.5\t".chars() {
+            scanner.scan_char(c);
+        }
+        assert_eq!(scanner.buffer(), "set a15");
+
+        scanner.clear_buffer();
+        scanner.char_policy = CharPolicy::Printable;
+        for c in "set a=1.5\t".chars() {
+            scanner.scan_char(c);
+        }
+        assert_eq!(scanner.buffer(), "set a=1.5");
+
+        scanner.clear_buffer();
+        scanner.char_policy = CharPolicy::Custom(|c| c.is_ascii_digit() || c == '.');
+        scanner.scan(Event::Paste("set a=1.5".to_owned()));
+        assert_eq!(scanner.buffer(), "1.5");
+    }
+
+    #[test]
+    fn paste_policies() {
+        let paste = |text: &str| Event::Paste(text.to_owned());
+
+        let mut scanner = scanner_with("set  red", 4);
+        scanner.paste_policy = PastePolicy::Reject;
+        assert!(matches!(scanner.scan(paste("color\n")), TokenType::None));
+        scanner.scan(paste("color"));
+        assert_eq!(scanner.buffer(), "set color red");
+        assert_eq!(scanner.cursor(), 9);
+
+        let mut scanner = scanner_with("", 0);
+        scanner.paste_policy = PastePolicy::Join;
+        scanner.scan(paste("set\r\ncolor\rred"));
+        assert_eq!(scanner.buffer(), "set color red");
+
+        let mut scanner = scanner_with("", 0);
+        scanner.paste_policy = PastePolicy::SubmitEachLine;
+        assert!(
+            matches!(scanner.scan(paste("one\ntwo\nthr")), TokenType::Token(token) if token == "one")
+        );
+        scanner.clear_buffer();
+        assert!(
+            matches!(scanner.scan_pasted_line(), Some(TokenType::Token(token)) if token == "two")
+        );
+        scanner.clear_buffer();
+        assert!(matches!(
+            scanner.scan_pasted_line(),
+            Some(TokenType::Continue)
+        ));
+        assert_eq!(scanner.buffer(), "thr");
+        assert!(scanner.scan_pasted_line().is_none());
+    }
+
+    #[test]
+    fn undo_and_redo_edits() {
+        let ctrl = |c| key(KeyCode::Char(c), KeyModifiers::CONTROL);
+        let mut scanner = scanner_with("", 0);
+        for c in "set red".chars() {
+            scanner.scan(key(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        scanner.scan(ctrl('w'));
+        scanner.update_buffer("some_token".to_owned());
+        assert_eq!(scanner.buffer(), "some_token");
+
+        scanner.scan(ctrl('z'));
+        assert_eq!(scanner.buffer(), "set ");
+        scanner.scan(ctrl('z'));
+        assert_eq!(scanner.buffer(), "set red");
+        scanner.scan(ctrl('z'));
+        assert_eq!(scanner.buffer(), "");
+        assert!(matches!(scanner.scan(ctrl('z')), TokenType::None));
+
+        scanner.scan(key(KeyCode::Char('_'), KeyModifiers::ALT));
+        assert_eq!(scanner.buffer(), "set red");
+        assert_eq!(scanner.cursor(), 7);
+
+        // cursor movement ends a group of inserted chars
+        scanner.scan(key(KeyCode::Char('s'), KeyModifiers::NONE));
+        scanner.scan(key(KeyCode::Left, KeyModifiers::NONE));
+        scanner.scan(key(KeyCode::Char('x'), KeyModifiers::NONE));
+        scanner.scan(ctrl('z'));
+        assert_eq!(scanner.buffer(), "set reds");
+    }
+
+    #[test]
+    fn ctrl_d() {
+        let ctrl_d = || key(KeyCode::Char('d'), KeyModifiers::CONTROL);
+        let mut scanner = scanner_with("ab", 0);
+        scanner.scan(ctrl_d());
+        assert_eq!(scanner.buffer(), "b");
+        scanner.scan(key(KeyCode::End, KeyModifiers::NONE));
+        assert!(matches!(scanner.scan(ctrl_d()), TokenType::None));
+        scanner.scan(key(KeyCode::Backspace, KeyModifiers::NONE));
+        assert!(matches!(scanner.scan(ctrl_d()), TokenType::Eof));
+    }
+
+    #[test]
+    fn rebound_keys() {
+        let mut scanner = scanner_with("some_token", 10);
+        scanner.keymap = Keymap::default()
+            .unbind(KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE))
+            .bind(
+                KeyEvent::new(KeyCode::Char('h'), KeyModifiers::CONTROL),
+                Action::BackwardDeleteChar,
+            )
+            .bind(
+                KeyEvent::new(KeyCode::F(1), KeyModifiers::NONE),
+                Action::EmitToken("help".to_owned()),
+            );
+
+        assert!(matches!(
+            scanner.scan(key(KeyCode::Backspace, KeyModifiers::NONE)),
+            TokenType::None
+        ));
+        scanner.scan(key(KeyCode::Char('h'), KeyModifiers::CONTROL));
+        assert_eq!(scanner.buffer(), "some_toke");
+        assert!(
+            matches!(scanner.scan(key(KeyCode::F(1), KeyModifiers::NONE)), TokenType::Emit(token) if token == "help")
+        );
+        assert_eq!(scanner.buffer(), "some_toke");
+    }
+
+    #[test]
+    fn altgr_chars() {
+        let mut scanner = Scanner::new();
+        // AltGr is reported as control and alt on Windows
+        let altgr = KeyModifiers::CONTROL | KeyModifiers::ALT;
+        scanner.scan(key(KeyCode::Char('{'), altgr));
+        scanner.scan(key(KeyCode::Char('\\'), altgr | KeyModifiers::SHIFT));
+        assert_eq!(scanner.buffer(), "{\\");
+
+        assert!(matches!(
+            scanner.scan(key(KeyCode::Char('b'), KeyModifiers::CONTROL)),
+            TokenType::None
+        ));
+        assert!(matches!(
+            scanner.scan(key(KeyCode::Char('b'), KeyModifiers::ALT)),
+            TokenType::None
+        ));
+        assert_eq!(scanner.buffer(), "{\\");
+    }
+
+    #[test]
+    fn vi_operators() {
+        let char_key = |c| key(KeyCode::Char(c), KeyModifiers::NONE);
+        let mut scanner = scanner_with("set color red", 13);
+        scanner.enable_vi_mode();
+
+        scanner.scan(key(KeyCode::Esc, KeyModifiers::NONE));
+        assert_eq!(scanner.vi_mode(), Some(ViMode::Normal));
+        assert_eq!(scanner.cursor(), 12);
+
+        scanner.scan(char_key('0'));
+        scanner.scan(char_key('w'));
+        scanner.scan(char_key('d'));
+        scanner.scan(char_key('w'));
+        assert_eq!(scanner.buffer(), "set red");
+        assert_eq!(scanner.cursor(), 4);
+
+        scanner.scan(char_key('y'));
+        scanner.scan(char_key('$'));
+        scanner.scan(char_key('0'));
+        scanner.scan(char_key('P'));
+        assert_eq!(scanner.buffer(), "redset red");
+        assert_eq!(scanner.cursor(), 2);
+
+        scanner.scan(char_key('0'));
+        scanner.scan(char_key('c'));
+        scanner.scan(char_key('w'));
+        assert_eq!(scanner.buffer(), " red");
+        assert_eq!(scanner.vi_mode(), Some(ViMode::Insert));
+        scanner.scan(char_key('d'));
+        assert_eq!(scanner.buffer(), "d red");
+    }
+
+    #[test]
+    fn cursor_moves_on_grapheme_boundaries() {
+        let mut scanner = scanner_with("ae\u{301}漢", 0);
+        scanner.scan_right();
+        scanner.scan_right();
+        assert_eq!(scanner.cursor(), 4);
+        scanner.scan_right();
+        assert_eq!(scanner.cursor(), 7);
+        assert!(matches!(scanner.scan_right(), TokenType::None));
+        scanner.scan_left();
+        scanner.scan_left();
+        assert_eq!(scanner.cursor(), 1);
+        scanner.scan_left();
+        assert!(matches!(scanner.scan_left(), TokenType::None));
+
+        scanner.scan_delete();
+        scanner.scan_delete();
+        assert_eq!(scanner.buffer(), "漢");
+        scanner.scan_end();
+        scanner.scan_backspace();
+        assert_eq!(scanner.buffer(), "");
     }
 }
@@ -0,0 +1,164 @@
+use crate::history::History;
+use crate::keymap::{self, Action, Keymap};
+use crossterm::event::{Event, KeyCode};
+
+// Outcome of an event received while searching.
+#[derive(Debug, PartialEq)]
+pub(crate) enum SearchEvent {
+    Accept,
+    Cancel,
+    None,
+}
+
+// State of a reverse incremental search through the history.
+#[derive(Debug)]
+pub(crate) struct Search {
+    pub(crate) query: String,
+    // Index of the matching history entry.
+    pub(crate) found: Option<usize>,
+    // Whether the query, or the last step to an older match, found nothing.
+    pub(crate) failing: bool,
+}
+
+impl Search {
+    pub(crate) fn new() -> Self {
+        Search {
+            query: String::new(),
+            found: None,
+            failing: false,
+        }
+    }
+
+    // Handles an event according to the keymap. Accepting the line accepts
+    // the match, aborting or interrupting cancels the search and searching
+    // the history again steps to an older match. Typed and pasted text
+    // narrows the search.
+    pub(crate) fn scan(
+        &mut self,
+        event: &Event,
+        keymap: &Keymap,
+        history: &History,
+    ) -> SearchEvent {
+        let key = match event {
+            Event::Key(key) => *key,
+            Event::Paste(text) => {
+                self.push_str(&text.replace('\n', " "), history);
+                return SearchEvent::None;
+            }
+            _ => return SearchEvent::None,
+        };
+        match (keymap.get(key), key.code) {
+            (Some(Action::AcceptLine), _) => return SearchEvent::Accept,
+            (Some(Action::Abort | Action::Interrupt), _) => {
+                self.found = None;
+                return SearchEvent::Cancel;
+            }
+            (Some(Action::ReverseSearchHistory), _) => self.older(history),
+            (Some(Action::BackwardDeleteChar), _) => self.pop(history),
+            (None, KeyCode::Char(c)) if keymap::is_text(key.modifiers) => {
+                self.push_str(c.encode_utf8(&mut [0; 4]), history)
+            }
+            _ => (),
+        }
+        SearchEvent::None
+    }
+
+    // Narrows the search, the current match is kept if it still matches.
+    pub(crate) fn push_str(&mut self, s: &str, history: &History) {
+        self.query.push_str(s);
+        let before = self.found.map(|idx| idx + 1).unwrap_or(history.len());
+        self.update(history.search(&self.query, before));
+    }
+
+    // Widens the search, starting again from the most recent entry.
+    pub(crate) fn pop(&mut self, history: &History) {
+        self.query.pop();
+        if self.query.is_empty() {
+            *self = Search::new();
+        } else {
+            self.update(history.search(&self.query, history.len()));
+        }
+    }
+
+    // Steps to the next older match.
+    pub(crate) fn older(&mut self, history: &History) {
+        let before = self.found.unwrap_or(history.len());
+        self.update(history.search(&self.query, before));
+    }
+
+    // A failed search keeps the previous match.
+    fn update(&mut self, found: Option<usize>) {
+        self.failing = found.is_none();
+        self.found = found.or(self.found);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyEvent, KeyModifiers};
+
+    #[test]
+    fn narrow_and_step() {
+        let mut history = History::new();
+        for line in ["set red", "get", "set blue", "reset"] {
+            history.add(line).unwrap();
+        }
+
+        let mut search = Search::new();
+        search.push_str("se", &history);
+        assert_eq!(search.found, Some(3));
+        search.push_str("t ", &history);
+        assert_eq!(search.found, Some(2));
+        search.older(&history);
+        assert_eq!(search.found, Some(0));
+        search.older(&history);
+        assert_eq!((search.found, search.failing), (Some(0), true));
+
+        search.push_str("x", &history);
+        assert_eq!((search.found, search.failing), (Some(0), true));
+        search.pop(&history);
+        assert_eq!((search.found, search.failing), (Some(2), false));
+    }
+
+    #[test]
+    fn rebound_keys() {
+        let mut history = History::new();
+        for line in ["set red", "get", "set blue"] {
+            history.add(line).unwrap();
+        }
+        let key = |code, modifiers| Event::Key(KeyEvent::new(code, modifiers));
+        let ctrl = |c| key(KeyCode::Char(c), KeyModifiers::CONTROL);
+        let keymap = Keymap::default()
+            .bind(
+                KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL),
+                Action::ReverseSearchHistory,
+            )
+            .unbind(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL));
+
+        let mut search = Search::new();
+        search.scan(
+            &key(KeyCode::Char('s'), KeyModifiers::NONE),
+            &keymap,
+            &history,
+        );
+        assert_eq!(search.found, Some(2));
+        search.scan(&ctrl('s'), &keymap, &history);
+        assert_eq!(search.found, Some(0));
+        search.scan(&ctrl('r'), &keymap, &history);
+        assert_eq!((search.query.as_str(), search.found), ("s", Some(0)));
+        assert_eq!(
+            search.scan(&key(KeyCode::Enter, KeyModifiers::NONE), &keymap, &history),
+            SearchEvent::Accept
+        );
+
+        // escape and ctrl+g abort, as well as the interrupt key
+        for cancel in [key(KeyCode::Esc, KeyModifiers::NONE), ctrl('g'), ctrl('c')] {
+            let mut search = Search::new();
+            search.scan(&Event::Paste("get".to_owned()), &keymap, &history);
+            assert_eq!(search.found, Some(1));
+            assert_eq!(search.scan(&cancel, &keymap, &history), SearchEvent::Cancel);
+            assert_eq!(search.found, None);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use crate::keymap;
+use crossterm::event::{Event, KeyCode, KeyModifiers};
+
+// Outcome of an event received while reading a secret.
+#[derive(Debug, PartialEq)]
+pub(crate) enum SecretEvent {
+    Edited,
+    Submit,
+    Cancel,
+    None,
+}
+
+// A secret being typed. It bypasses the scanner, so that it never reaches
+// the autocomplete, the kill ring or the edit history.
+#[derive(Debug)]
+pub(crate) struct Secret {
+    value: String,
+}
+
+impl Secret {
+    pub(crate) fn new() -> Self {
+        Secret {
+            value: String::new(),
+        }
+    }
+
+    pub(crate) fn scan(&mut self, event: &Event) -> SecretEvent {
+        match event {
+            Event::Key(key) => match key.code {
+                KeyCode::Enter => SecretEvent::Submit,
+                KeyCode::Esc => SecretEvent::Cancel,
+                KeyCode::Backspace => {
+                    self.value.pop();
+                    SecretEvent::Edited
+                }
+                KeyCode::Char(c) if keymap::is_text(key.modifiers) => {
+                    self.push(c);
+                    SecretEvent::Edited
+                }
+                KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => match c {
+                    'c' => SecretEvent::Cancel,
+                    'd' if self.value.is_empty() => SecretEvent::Cancel,
+                    'u' => {
+                        self.value.clear();
+                        SecretEvent::Edited
+                    }
+                    _ => SecretEvent::None,
+                },
+                _ => SecretEvent::None,
+            },
+            // Only the first line of a paste is kept, a password manager may
+            // append a newline to the copied secret.
+            Event::Paste(text) => {
+                text.lines()
+                    .next()
+                    .unwrap_or_default()
+                    .chars()
+                    .for_each(|c| self.push(c));
+                SecretEvent::Edited
+            }
+            _ => SecretEvent::None,
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        if !c.is_control() {
+            self.value.push(c);
+        }
+    }
+
+    // Number of characters typed so far, used to echo the mask.
+    pub(crate) fn len(&self) -> usize {
+        self.value.chars().count()
+    }
+
+    pub(crate) fn into_inner(self) -> String {
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyEvent;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Event {
+        Event::Key(KeyEvent::new(code, modifiers))
+    }
+
+    fn chars(secret: &mut Secret, s: &str) {
+        s.chars().for_each(|c| {
+            secret.scan(&key(KeyCode::Char(c), KeyModifiers::NONE));
+        });
+    }
+
+    #[test]
+    fn type_and_edit() {
+        let mut secret = Secret::new();
+        chars(&mut secret, "hunter2");
+        secret.scan(&key(KeyCode::Backspace, KeyModifiers::NONE));
+        assert_eq!(secret.len(), 6);
+        assert_eq!(
+            secret.scan(&key(KeyCode::Char('a'), KeyModifiers::CONTROL)),
+            SecretEvent::None
+        );
+        secret.scan(&key(KeyCode::Char('u'), KeyModifiers::CONTROL));
+        assert_eq!(secret.len(), 0);
+
+        secret.scan(&Event::Paste("pässword\n".to_owned()));
+        assert_eq!(
+            secret.scan(&key(KeyCode::Char('d'), KeyModifiers::CONTROL)),
+            SecretEvent::None
+        );
+        assert_eq!(
+            secret.scan(&key(KeyCode::Enter, KeyModifiers::NONE)),
+            SecretEvent::Submit
+        );
+        // AltGr is reported as control and alt on Windows
+        secret.scan(&key(
+            KeyCode::Char('@'),
+            KeyModifiers::CONTROL | KeyModifiers::ALT,
+        ));
+        assert_eq!(
+            secret.scan(&key(KeyCode::Char('x'), KeyModifiers::ALT)),
+            SecretEvent::None
+        );
+        assert_eq!(secret.len(), 9);
+        assert_eq!(secret.into_inner(), "pässword@");
+    }
+
+    #[test]
+    fn cancel() {
+        let mut secret = Secret::new();
+        assert_eq!(
+            secret.scan(&key(KeyCode::Char('d'), KeyModifiers::CONTROL)),
+            SecretEvent::Cancel
+        );
+        chars(&mut secret, "abc");
+        assert_eq!(
+            secret.scan(&key(KeyCode::Esc, KeyModifiers::NONE)),
+            SecretEvent::Cancel
+        );
+        assert_eq!(
+            secret.scan(&key(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            SecretEvent::Cancel
+        );
+    }
+}
@@ -1,7 +1,8 @@
 use crate::error::Result;
-use crossterm::cursor::{self, MoveLeft, MoveTo, RestorePosition, SavePosition};
+use crossterm::cursor::{MoveRight, MoveTo, MoveUp};
 use crossterm::event;
-use crossterm::event::Event;
+use crossterm::event::{DisableBracketedPaste, EnableBracketedPaste, Event};
+use crossterm::style::Print;
 use crossterm::terminal::{
     self, disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
     LeaveAlternateScreen,
@@ -42,21 +43,6 @@ impl Terminal {
         Ok(())
     }
 
-    pub(super) fn clear_line(&self) -> Result<()> {
-        execute!(stdout(), Clear(ClearType::UntilNewLine))?;
-        Ok(())
-    }
-
-    pub(super) fn save_cursor(&self) -> Result<()> {
-        execute!(stdout(), SavePosition)?;
-        Ok(())
-    }
-
-    pub(super) fn restore_cursor(&self) -> Result<()> {
-        execute!(stdout(), RestorePosition)?;
-        Ok(())
-    }
-
     pub(super) fn enable_alternate_screen(&mut self) -> Result<()> {
         execute!(stdout(), EnterAlternateScreen)?;
         execute!(stdout(), MoveTo(0, 0))?;
@@ -80,42 +66,44 @@ impl Terminal {
         Ok(())
     }
 
-    pub(crate) fn flush(&mut self) -> Result<()> {
-        self.stdout.flush()?;
+    pub(crate) fn enable_bracketed_paste(&self) -> Result<()> {
+        execute!(stdout(), EnableBracketedPaste)?;
         Ok(())
     }
 
-    pub(crate) fn goto_beginning_of_line(&mut self) -> Result<()> {
-        execute!(stdout(), MoveLeft(cursor::position()?.0))?;
+    pub(crate) fn disable_bracketed_paste(&self) -> Result<()> {
+        execute!(stdout(), DisableBracketedPaste)?;
+        Ok(())
+    }
+
+    pub(crate) fn flush(&mut self) -> Result<()> {
+        self.stdout.flush()?;
         Ok(())
     }
 
     pub(crate) fn size(&self) -> (u16, u16) {
-        if let Ok(size) = terminal::size() {
-            size
-        } else {
-            (0, 0)
-        }
+        terminal::size().unwrap_or_default()
     }
 
-    pub(crate) fn get_cursor_pos(&self) -> (u16, u16) {
-        if let Ok(pos) = cursor::position() {
-            pos
-        } else {
-            (0, 0)
+    pub(crate) fn move_up(&mut self, rows: usize) -> Result<()> {
+        if rows > 0 {
+            execute!(stdout(), MoveUp(rows as u16))?;
         }
+        Ok(())
     }
 
-    pub(crate) fn scroll_up(&mut self, step: u16) -> Result<()> {
-        stdout()
-            .execute(terminal::ScrollUp(step))?
-            .execute(cursor::MoveUp(step))?;
+    // Moves the cursor down, scrolling the terminal when on its last row.
+    pub(crate) fn move_down(&mut self, rows: usize) -> Result<()> {
+        execute!(stdout(), Print("\n".repeat(rows)))?;
         Ok(())
     }
 
-    pub(crate) fn backspace(&mut self) -> Result<()> {
-        execute!(stdout(), cursor::MoveLeft(1))?;
-        self.clear_line()?;
+    // Moves the cursor to the given zero based column of the current line.
+    pub(crate) fn goto_column(&mut self, column: u16) -> Result<()> {
+        execute!(stdout(), Print('\r'))?;
+        if column > 0 {
+            execute!(stdout(), MoveRight(column))?;
+        }
         Ok(())
     }
 
@@ -124,6 +112,7 @@ impl Terminal {
             AlternateScreen::Enabled => self.disable_alternate_screen().unwrap(),
             AlternateScreen::Disabled => (),
         }
+        self.disable_bracketed_paste().unwrap();
         self.disable_raw_mode().unwrap();
         std::process::exit(0);
     }
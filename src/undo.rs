@@ -0,0 +1,74 @@
+// A state of the line being edited: its content and the cursor position.
+pub(crate) type Snapshot = (String, usize);
+
+// Undo and redo stacks for the edits of the current line.
+#[derive(Debug)]
+pub(crate) struct EditHistory {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+impl EditHistory {
+    pub(crate) fn new() -> Self {
+        EditHistory {
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    // Records the state preceding an edit. A new edit can no longer be redone.
+    pub(crate) fn record(&mut self, before: Snapshot) {
+        self.undo.push(before);
+        self.redo.clear();
+    }
+
+    // Returns the state preceding the last edit, saving the current one to be
+    // redone.
+    pub(crate) fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let previous = self.undo.pop()?;
+        self.redo.push(current);
+        Some(previous)
+    }
+
+    // Returns the state before the last undo, saving the current one to be
+    // undone again.
+    pub(crate) fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let next = self.redo.pop()?;
+        self.undo.push(current);
+        Some(next)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(s: &str) -> Snapshot {
+        (s.to_owned(), s.len())
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut history = EditHistory::new();
+        assert_eq!(history.undo(snapshot("")), None);
+
+        history.record(snapshot(""));
+        history.record(snapshot("set"));
+        assert_eq!(history.undo(snapshot("set red")), Some(snapshot("set")));
+        assert_eq!(history.undo(snapshot("set")), Some(snapshot("")));
+        assert_eq!(history.undo(snapshot("")), None);
+        assert_eq!(history.redo(snapshot("")), Some(snapshot("set")));
+        assert_eq!(history.redo(snapshot("set")), Some(snapshot("set red")));
+        assert_eq!(history.redo(snapshot("set red")), None);
+
+        // a new edit drops the redo stack
+        history.undo(snapshot("set red"));
+        history.record(snapshot("set"));
+        assert_eq!(history.redo(snapshot("set blue")), None);
+    }
+}
@@ -0,0 +1,79 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+// Number of terminal columns needed to display the string.
+pub(crate) fn width(s: &str) -> usize {
+    UnicodeWidthStr::width(s)
+}
+
+// Byte offset of the grapheme cluster ending at `idx`, if any.
+pub(crate) fn prev_grapheme(s: &str, idx: usize) -> Option<usize> {
+    s[..idx]
+        .grapheme_indices(true)
+        .next_back()
+        .map(|(start, _)| start)
+}
+
+// Byte offset right after the grapheme cluster starting at `idx`, if any.
+pub(crate) fn next_grapheme(s: &str, idx: usize) -> Option<usize> {
+    s[idx..]
+        .graphemes(true)
+        .next()
+        .map(|grapheme| idx + grapheme.len())
+}
+
+// Returns the longest prefix of the string fitting in the display width.
+pub(crate) fn truncate(s: &str, max_width: usize) -> &str {
+    let mut end = 0;
+    let mut total = 0;
+    for (idx, grapheme) in s.grapheme_indices(true) {
+        total += width(grapheme);
+        if total > max_width {
+            break;
+        }
+        end = idx + grapheme.len();
+    }
+    &s[..end]
+}
+
+// Removes the last grapheme cluster of the string.
+pub(crate) fn pop_grapheme(s: &mut String) {
+    if let Some(start) = prev_grapheme(s, s.len()) {
+        s.truncate(start);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_and_combined_graphemes() {
+        // 'e' followed by a combining acute accent, then a wide CJK char
+        let s = "e\u{301}漢a";
+        assert_eq!(width(s), 4);
+        assert_eq!(next_grapheme(s, 0), Some(3));
+        assert_eq!(next_grapheme(s, 3), Some(6));
+        assert_eq!(next_grapheme(s, 7), None);
+        assert_eq!(prev_grapheme(s, 6), Some(3));
+        assert_eq!(prev_grapheme(s, 3), Some(0));
+        assert_eq!(prev_grapheme(s, 0), None);
+
+        let mut s = s.to_owned();
+        pop_grapheme(&mut s);
+        pop_grapheme(&mut s);
+        assert_eq!(s, "e\u{301}");
+        pop_grapheme(&mut s);
+        assert_eq!(s, "");
+    }
+
+    #[test]
+    fn truncate_to_width() {
+        let s = "e\u{301}漢a";
+        assert_eq!(truncate(s, 0), "");
+        assert_eq!(truncate(s, 1), "e\u{301}");
+        assert_eq!(truncate(s, 2), "e\u{301}");
+        assert_eq!(truncate(s, 3), "e\u{301}漢");
+        assert_eq!(truncate(s, 10), s);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::unicode;
+use crossterm::event::{KeyCode, KeyEvent};
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ViMode {
+    Insert,
+    Normal,
+}
+
+impl ViMode {
+    // Indicator printed in front of the prompt.
+    pub(crate) fn indicator(&self) -> &'static str {
+        match self {
+            ViMode::Insert => "[I]",
+            ViMode::Normal => "[N]",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Motion {
+    Left,
+    Right,
+    WordForward,
+    WordBackward,
+    // End of the word under the cursor, used by `cw`.
+    WordEnd,
+    LineStart,
+    LineEnd,
+    Line,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum InsertAt {
+    Cursor,
+    AfterCursor,
+    LineStart,
+    LineEnd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ViCommand {
+    Move(Motion),
+    Operate(Operator, Motion),
+    Insert(InsertAt),
+    Put { before: bool },
+    Undo,
+    History { previous: bool },
+    // An operator is waiting for its motion.
+    Pending,
+    None,
+}
+
+// Vi editing state, tracking the current mode and any operator awaiting a motion.
+#[derive(Debug)]
+pub(crate) struct ViState {
+    pub(crate) mode: ViMode,
+    pending: Option<Operator>,
+}
+
+impl ViState {
+    pub(crate) fn new() -> Self {
+        ViState {
+            mode: ViMode::Insert,
+            pending: None,
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.mode = ViMode::Insert;
+        self.pending = None;
+    }
+
+    // Parses a key pressed in normal mode into a command.
+    pub(crate) fn normal_key(&mut self, key: KeyEvent) -> ViCommand {
+        let c = match key.code {
+            KeyCode::Char(c) => c,
+            KeyCode::Left => 'h',
+            KeyCode::Right => 'l',
+            KeyCode::Home => '0',
+            KeyCode::End => '$',
+            KeyCode::Up => 'k',
+            KeyCode::Down => 'j',
+            _ => {
+                self.pending = None;
+                return ViCommand::None;
+            }
+        };
+
+        if let Some(operator) = self.pending.take() {
+            let motion = match (operator, c) {
+                (Operator::Delete, 'd') | (Operator::Change, 'c') | (Operator::Yank, 'y') => {
+                    Motion::Line
+                }
+                (Operator::Change, 'w') => Motion::WordEnd,
+                _ => match to_motion(c) {
+                    Some(motion) => motion,
+                    None => return ViCommand::None,
+                },
+            };
+            return ViCommand::Operate(operator, motion);
+        }
+
+        if let Some(motion) = to_motion(c) {
+            return ViCommand::Move(motion);
+        }
+
+        match c {
+            'd' => self.pend(Operator::Delete),
+            'c' => self.pend(Operator::Change),
+            'y' => self.pend(Operator::Yank),
+            'x' => ViCommand::Operate(Operator::Delete, Motion::Right),
+            'D' => ViCommand::Operate(Operator::Delete, Motion::LineEnd),
+            'C' => ViCommand::Operate(Operator::Change, Motion::LineEnd),
+            'i' => ViCommand::Insert(InsertAt::Cursor),
+            'a' => ViCommand::Insert(InsertAt::AfterCursor),
+            'I' => ViCommand::Insert(InsertAt::LineStart),
+            'A' => ViCommand::Insert(InsertAt::LineEnd),
+            'p' => ViCommand::Put { before: false },
+            'P' => ViCommand::Put { before: true },
+            'u' => ViCommand::Undo,
+            'k' => ViCommand::History { previous: true },
+            'j' => ViCommand::History { previous: false },
+            _ => ViCommand::None,
+        }
+    }
+
+    fn pend(&mut self, operator: Operator) -> ViCommand {
+        self.pending = Some(operator);
+        ViCommand::Pending
+    }
+}
+
+fn to_motion(c: char) -> Option<Motion> {
+    match c {
+        'h' => Some(Motion::Left),
+        'l' => Some(Motion::Right),
+        'w' => Some(Motion::WordForward),
+        'b' => Some(Motion::WordBackward),
+        '0' => Some(Motion::LineStart),
+        '$' => Some(Motion::LineEnd),
+        _ => None,
+    }
+}
+
+#[derive(PartialEq)]
+enum CharClass {
+    Space,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Space
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+fn grapheme_class(grapheme: &str) -> CharClass {
+    grapheme
+        .chars()
+        .next()
+        .map(class)
+        .unwrap_or(CharClass::Space)
+}
+
+// Returns the byte offset the motion moves the cursor to.
+pub(crate) fn motion_target(buffer: &str, cursor: usize, motion: Motion) -> usize {
+    match motion {
+        Motion::Left => unicode::prev_grapheme(buffer, cursor).unwrap_or(cursor),
+        Motion::Right => unicode::next_grapheme(buffer, cursor).unwrap_or(cursor),
+        Motion::WordForward => {
+            let mut graphemes = buffer[cursor..].grapheme_indices(true).peekable();
+            if let Some(&(_, first)) = graphemes.peek() {
+                let first = grapheme_class(first);
+                if first != CharClass::Space {
+                    while graphemes
+                        .next_if(|&(_, g)| grapheme_class(g) == first)
+                        .is_some()
+                    {}
+                }
+            }
+            while graphemes
+                .next_if(|&(_, g)| grapheme_class(g) == CharClass::Space)
+                .is_some()
+            {}
+            graphemes
+                .peek()
+                .map(|&(idx, _)| cursor + idx)
+                .unwrap_or(buffer.len())
+        }
+        Motion::WordEnd => {
+            let mut graphemes = buffer[cursor..].grapheme_indices(true).peekable();
+            if let Some(&(_, first)) = graphemes.peek() {
+                let first = grapheme_class(first);
+                while graphemes
+                    .next_if(|&(_, g)| grapheme_class(g) == first)
+                    .is_some()
+                {}
+            }
+            graphemes
+                .peek()
+                .map(|&(idx, _)| cursor + idx)
+                .unwrap_or(buffer.len())
+        }
+        Motion::WordBackward => {
+            let mut graphemes = buffer[..cursor].grapheme_indices(true).rev().peekable();
+            while graphemes
+                .next_if(|&(_, g)| grapheme_class(g) == CharClass::Space)
+                .is_some()
+            {}
+            let mut target = graphemes.peek().map(|&(idx, _)| idx).unwrap_or(0);
+            if let Some(&(_, first)) = graphemes.peek() {
+                let first = grapheme_class(first);
+                while let Some((idx, _)) = graphemes.next_if(|&(_, g)| grapheme_class(g) == first) {
+                    target = idx;
+                }
+            }
+            target
+        }
+        Motion::LineStart | Motion::Line => 0,
+        Motion::LineEnd => buffer.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    fn key(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn word_motions() {
+        let buffer = "set color.fg  red";
+        assert_eq!(motion_target(buffer, 0, Motion::WordForward), 4);
+        assert_eq!(motion_target(buffer, 4, Motion::WordForward), 9);
+        assert_eq!(motion_target(buffer, 9, Motion::WordForward), 10);
+        assert_eq!(motion_target(buffer, 10, Motion::WordForward), 14);
+        assert_eq!(motion_target(buffer, 14, Motion::WordForward), 17);
+        assert_eq!(motion_target(buffer, 4, Motion::WordEnd), 9);
+
+        assert_eq!(motion_target(buffer, 17, Motion::WordBackward), 14);
+        assert_eq!(motion_target(buffer, 14, Motion::WordBackward), 10);
+        assert_eq!(motion_target(buffer, 10, Motion::WordBackward), 9);
+        assert_eq!(motion_target(buffer, 6, Motion::WordBackward), 4);
+        assert_eq!(motion_target(buffer, 4, Motion::WordBackward), 0);
+        assert_eq!(motion_target(buffer, 0, Motion::WordBackward), 0);
+
+        // combining marks belong to the word they follow
+        let buffer = "cafe\u{301} noir";
+        assert_eq!(motion_target(buffer, 0, Motion::WordForward), 7);
+        assert_eq!(motion_target(buffer, 7, Motion::WordBackward), 0);
+    }
+
+    #[test]
+    fn operators_wait_for_motion() {
+        let mut vi = ViState::new();
+        assert_eq!(vi.normal_key(key('d')), ViCommand::Pending);
+        assert_eq!(
+            vi.normal_key(key('w')),
+            ViCommand::Operate(Operator::Delete, Motion::WordForward)
+        );
+        assert_eq!(
+            vi.normal_key(key('w')),
+            ViCommand::Move(Motion::WordForward)
+        );
+
+        vi.normal_key(key('c'));
+        assert_eq!(
+            vi.normal_key(key('w')),
+            ViCommand::Operate(Operator::Change, Motion::WordEnd)
+        );
+        vi.normal_key(key('y'));
+        assert_eq!(
+            vi.normal_key(key('y')),
+            ViCommand::Operate(Operator::Yank, Motion::Line)
+        );
+
+        // an invalid motion cancels the operator
+        vi.normal_key(key('d'));
+        assert_eq!(vi.normal_key(key('z')), ViCommand::None);
+        assert_eq!(vi.normal_key(key('$')), ViCommand::Move(Motion::LineEnd));
+    }
+}